@@ -0,0 +1,206 @@
+//! Renders the stages the raytracer was built in, each from its scene file
+//! in `scenes/` with the lighting model cut down to what that stage had:
+//!
+//! ```sh
+//! cargo run --release --example stages -- shadows -o shadows.png
+//! ```
+//!
+//! Without `-o` the render is shown in a window. Each stage prints where its
+//! screenshot is committed, to compare the two side by side.
+
+use cgfs::canvas::{Canvas, Framebuffer, HdrCanvas, Quantization, Rgb};
+use cgfs::export;
+use cgfs::raytracer::{self, LightingModel, RenderSettings, World};
+use cgfs::scene_file::{self, SceneError};
+use std::path::Path;
+use std::process;
+
+const WHITE: Rgb = Rgb {
+    red: 255.0,
+    green: 255.0,
+    blue: 255.0,
+};
+
+const BLACK: Rgb = Rgb {
+    red: 0.0,
+    green: 0.0,
+    blue: 0.0,
+};
+
+/// The first stages multiplied the surface color by the sum of all the
+/// light, highlights included, and had no shadows or reflections yet.
+const FIRST_STAGES: LightingModel = LightingModel {
+    diffuse: true,
+    specular: true,
+    shadows: false,
+    reflections: false,
+    tinted_specular: true,
+};
+
+struct Stage {
+    name: &'static str,
+    description: &'static str,
+    /// In `scenes/`.
+    scene: &'static str,
+    lighting: LightingModel,
+    background: Rgb,
+    /// In `stages/raytracer/`.
+    screenshot: &'static str,
+}
+
+const STAGES: [Stage; 6] = [
+    Stage {
+        name: "basic",
+        description: "Basic raytracing, flat colors",
+        scene: "basic.toml",
+        lighting: LightingModel {
+            diffuse: false,
+            specular: false,
+            ..FIRST_STAGES
+        },
+        background: WHITE,
+        screenshot: "stage_1/stage_1.png",
+    },
+    Stage {
+        name: "diffuse",
+        description: "Diffuse reflection",
+        scene: "diffuse.toml",
+        lighting: LightingModel {
+            specular: false,
+            ..FIRST_STAGES
+        },
+        background: WHITE,
+        screenshot: "stage_2/diffused_only/stage_2_diffused_only.png",
+    },
+    Stage {
+        name: "specular",
+        description: "Diffuse and specular reflection",
+        scene: "specular.toml",
+        lighting: FIRST_STAGES,
+        background: WHITE,
+        screenshot: "stage_2/diffused_specular/stage_2_diffused_specular.png",
+    },
+    Stage {
+        name: "shadows",
+        description: "Shadows",
+        scene: "specular.toml",
+        lighting: LightingModel {
+            shadows: true,
+            ..FIRST_STAGES
+        },
+        background: WHITE,
+        screenshot: "stage_3/shadows_only/shadows_only.png",
+    },
+    Stage {
+        name: "reflections",
+        description: "Shadows and reflections",
+        scene: "reflections.toml",
+        lighting: LightingModel {
+            shadows: true,
+            reflections: true,
+            ..FIRST_STAGES
+        },
+        background: BLACK,
+        screenshot: "stage_3/shadows_reflection/shadows_reflection.png",
+    },
+    Stage {
+        name: "camera",
+        description: "Arbitrary camera position and orientation",
+        scene: "camera.toml",
+        lighting: LightingModel {
+            shadows: true,
+            reflections: true,
+            ..FIRST_STAGES
+        },
+        background: BLACK,
+        screenshot: "stage_4/camera.png",
+    },
+];
+
+impl Stage {
+    fn world(&self, root: &Path) -> Result<World, SceneError> {
+        let world = scene_file::load(root.join("scenes").join(self.scene))?;
+        Ok(world
+            .with_lighting(self.lighting)
+            .with_background(self.background))
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: stages STAGE [-o FILE]\n\nStages:");
+    for stage in &STAGES {
+        eprintln!("  {:<12} {}", stage.name, stage.description);
+    }
+    process::exit(2);
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let name = args.next().unwrap_or_else(|| usage());
+    let stage = match STAGES.iter().find(|stage| stage.name == name) {
+        Some(stage) => stage,
+        None => usage(),
+    };
+    let output = match (args.next().as_deref(), args.next()) {
+        (None, _) => None,
+        (Some("-o"), Some(path)) => Some(path),
+        _ => usage(),
+    };
+
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let world = match stage.world(root) {
+        Ok(world) => world,
+        Err(e) => {
+            eprintln!("Could not load the scene: {}", e);
+            process::exit(1);
+        }
+    };
+
+    let mut canvas = HdrCanvas::new(800, 800);
+    canvas.clear_canvas(&WHITE);
+    raytracer::render(&world, &mut canvas, &RenderSettings::default());
+
+    let screenshot = root.join("stages/raytracer").join(stage.screenshot);
+    eprintln!(
+        "{}, compare with {}",
+        stage.description,
+        screenshot.display()
+    );
+
+    let quantization = Quantization::default();
+    match output {
+        Some(path) => {
+            if let Err(e) = export::save_hdr(&canvas, &path, &quantization) {
+                eprintln!("Could not write {}: {}", path, e);
+                process::exit(1);
+            }
+        }
+        None => match Canvas::with_pixels("Raytracer", canvas.quantize(&quantization)) {
+            Ok(mut window) => window.display_until_exit(),
+            Err(e) => eprintln!("Could not open a window, skipping display: {}", e),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_stage_renders() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        for stage in &STAGES {
+            let world = stage.world(root).unwrap();
+            let mut canvas = HdrCanvas::new(24, 24);
+            raytracer::render(&world, &mut canvas, &RenderSettings::default());
+            assert!(
+                canvas
+                    .buffer()
+                    .iter()
+                    .any(|pixel| *pixel != stage.background),
+                "{} rendered only its background",
+                stage.name
+            );
+        }
+    }
+}
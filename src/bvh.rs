@@ -0,0 +1,531 @@
+//! Bounding volume hierarchies over anything with an axis-aligned bounding box.
+
+use crate::math::Vec3;
+use crate::object::Ray;
+
+/// How ray queries find the objects they hit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Acceleration {
+    /// Walk a bounding volume hierarchy.
+    #[default]
+    Bvh,
+    /// Test every object, slow but handy to check the BVH against.
+    BruteForce,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Contains nothing, the identity for [`Aabb::union`].
+    pub const EMPTY: Aabb = Aabb {
+        min: Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+        max: Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+    };
+
+    pub fn new(min: Vec3, max: Vec3) -> Aabb {
+        Aabb { min, max }
+    }
+
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a Vec3>) -> Aabb {
+        points
+            .into_iter()
+            .fold(Aabb::EMPTY, |aabb, point| aabb.grow(point))
+    }
+
+    pub fn grow(&self, p: &Vec3) -> Aabb {
+        Aabb {
+            min: Vec3::new(
+                self.min.x.min(p.x),
+                self.min.y.min(p.y),
+                self.min.z.min(p.z),
+            ),
+            max: Vec3::new(
+                self.max.x.max(p.x),
+                self.max.y.max(p.y),
+                self.max.z.max(p.z),
+            ),
+        }
+    }
+
+    pub fn union(&self, b: &Aabb) -> Aabb {
+        self.grow(&b.min).grow(&b.max)
+    }
+
+    /// Grows the box by `margin` on every side, so flat boxes get some volume.
+    pub fn padded(&self, margin: f64) -> Aabb {
+        let margin = Vec3::new(margin, margin, margin);
+        Aabb {
+            min: self.min - margin,
+            max: self.max + margin,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let d = self.max - self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    fn axis(v: &Vec3, axis: usize) -> f64 {
+        match axis {
+            0 => v.x,
+            1 => v.y,
+            _ => v.z,
+        }
+    }
+
+    /// Where a ray enters the box within `min_t..=max_t`, using the slab test.
+    ///
+    /// `inverse_direction` is `1 / ray.direction` per component, passed in
+    /// because it is shared by every box a ray is tested against.
+    pub fn intersect(
+        &self,
+        ray: &Ray,
+        inverse_direction: &Vec3,
+        min_t: f64,
+        max_t: f64,
+    ) -> Option<f64> {
+        let (mut near, mut far) = (min_t, max_t);
+        for axis in 0..3 {
+            let origin = Aabb::axis(&ray.origin, axis);
+            let inverse = Aabb::axis(inverse_direction, axis);
+            let mut t0 = (Aabb::axis(&self.min, axis) - origin) * inverse;
+            let mut t1 = (Aabb::axis(&self.max, axis) - origin) * inverse;
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // Written so NaNs, from rays running inside a slab boundary, are ignored
+            if t0 > near {
+                near = t0;
+            }
+            if t1 < far {
+                far = t1;
+            }
+            if near > far {
+                return None;
+            }
+        }
+        Some(near)
+    }
+}
+
+/// Primitives left in a leaf when splitting doesn't pay off any more.
+const MAX_LEAF_SIZE: usize = 4;
+/// Above this, nodes are always split even when SAH says not to.
+const MAX_FORCED_LEAF_SIZE: usize = 32;
+const BINS: usize = 16;
+/// Cost of visiting a node relative to intersecting one primitive.
+const TRAVERSAL_COST: f64 = 0.5;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Leaf {
+        bounds: Aabb,
+        first: usize,
+        count: usize,
+    },
+    Interior {
+        bounds: Aabb,
+        left: usize,
+        right: usize,
+    },
+}
+
+impl Node {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// A BVH over primitives identified by their index, built with the surface
+/// area heuristic (SAH).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    /// Primitive indices, every leaf owns a contiguous range of them
+    indices: Vec<usize>,
+}
+
+impl Bvh {
+    /// Builds the hierarchy over primitives with the given bounding boxes.
+    pub fn build(bounds: &[Aabb]) -> Bvh {
+        let mut bvh = Bvh {
+            nodes: Vec::new(),
+            indices: (0..bounds.len()).collect(),
+        };
+        if !bounds.is_empty() {
+            let centroids: Vec<Vec3> = bounds.iter().map(Aabb::centroid).collect();
+            bvh.build_node(bounds, &centroids, 0, bounds.len());
+        }
+        bvh
+    }
+
+    /// Bounds of everything in the hierarchy, `None` if it is empty.
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.nodes.first().map(|node| *node.bounds())
+    }
+
+    fn build_node(
+        &mut self,
+        bounds: &[Aabb],
+        centroids: &[Vec3],
+        first: usize,
+        count: usize,
+    ) -> usize {
+        let range = first..first + count;
+        let node_bounds = self.indices[range.clone()]
+            .iter()
+            .fold(Aabb::EMPTY, |aabb, &i| aabb.union(&bounds[i]));
+
+        let index = self.nodes.len();
+        self.nodes.push(Node::Leaf {
+            bounds: node_bounds,
+            first,
+            count,
+        });
+        if count <= MAX_LEAF_SIZE {
+            return index;
+        }
+
+        let centroid_bounds =
+            Aabb::from_points(self.indices[range.clone()].iter().map(|&i| &centroids[i]));
+        let (axis, split, cost) = match self.best_split(bounds, centroids, &range, &centroid_bounds)
+        {
+            Some(best) => best,
+            // All centroids coincide, nothing to split on
+            None if count <= MAX_FORCED_LEAF_SIZE => return index,
+            None => (0, f64::NAN, f64::INFINITY),
+        };
+
+        let leaf_cost = count as f64;
+        let relative_cost =
+            TRAVERSAL_COST + cost / node_bounds.surface_area().max(f64::MIN_POSITIVE);
+        if relative_cost >= leaf_cost && count <= MAX_FORCED_LEAF_SIZE {
+            return index;
+        }
+
+        // Partition the range around the split plane
+        let slice = &mut self.indices[range];
+        let mut middle = 0;
+        for i in 0..slice.len() {
+            if Aabb::axis(&centroids[slice[i]], axis) < split {
+                slice.swap(i, middle);
+                middle += 1;
+            }
+        }
+        if middle == 0 || middle == count {
+            // Degenerate split, fall back to halving along the widest axis
+            let extent = centroid_bounds.max - centroid_bounds.min;
+            let axis = if extent.x >= extent.y && extent.x >= extent.z {
+                0
+            } else if extent.y >= extent.z {
+                1
+            } else {
+                2
+            };
+            slice.sort_by(|&a, &b| {
+                Aabb::axis(&centroids[a], axis).total_cmp(&Aabb::axis(&centroids[b], axis))
+            });
+            middle = count / 2;
+        }
+
+        let left = self.build_node(bounds, centroids, first, middle);
+        let right = self.build_node(bounds, centroids, first + middle, count - middle);
+        self.nodes[index] = Node::Interior {
+            bounds: node_bounds,
+            left,
+            right,
+        };
+        index
+    }
+
+    /// Axis, split position and SAH cost (unnormalized) of the cheapest
+    /// binned split of `range`.
+    fn best_split(
+        &self,
+        bounds: &[Aabb],
+        centroids: &[Vec3],
+        range: &std::ops::Range<usize>,
+        centroid_bounds: &Aabb,
+    ) -> Option<(usize, f64, f64)> {
+        let mut best: Option<(usize, f64, f64)> = None;
+
+        for axis in 0..3 {
+            let low = Aabb::axis(&centroid_bounds.min, axis);
+            let high = Aabb::axis(&centroid_bounds.max, axis);
+            if high - low <= 0.0 {
+                continue;
+            }
+            let bin_of = |c: f64| (((c - low) / (high - low) * BINS as f64) as usize).min(BINS - 1);
+
+            let mut bins = [(Aabb::EMPTY, 0usize); BINS];
+            for &i in &self.indices[range.clone()] {
+                let bin = &mut bins[bin_of(Aabb::axis(&centroids[i], axis))];
+                bin.0 = bin.0.union(&bounds[i]);
+                bin.1 += 1;
+            }
+
+            // Sweep from the right, then evaluate every split from the left
+            let mut right_area = [0.0; BINS];
+            let mut right_count = [0; BINS];
+            let (mut aabb, mut count) = (Aabb::EMPTY, 0);
+            for b in (1..BINS).rev() {
+                aabb = aabb.union(&bins[b].0);
+                count += bins[b].1;
+                right_area[b] = aabb.surface_area();
+                right_count[b] = count;
+            }
+
+            let (mut aabb, mut count) = (Aabb::EMPTY, 0);
+            for b in 1..BINS {
+                aabb = aabb.union(&bins[b - 1].0);
+                count += bins[b - 1].1;
+                if count == 0 || right_count[b] == 0 {
+                    continue;
+                }
+                let cost =
+                    aabb.surface_area() * count as f64 + right_area[b] * right_count[b] as f64;
+                if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                    let split = low + (high - low) * b as f64 / BINS as f64;
+                    best = Some((axis, split, cost));
+                }
+            }
+        }
+        best
+    }
+
+    /// Visits the primitives whose boxes `ray` passes through, nearest boxes
+    /// first.
+    ///
+    /// `hit` gets a primitive index and the current `max_t`, and returns the
+    /// new, closer `max_t` if it hit the primitive. Boxes behind that are
+    /// skipped.
+    pub fn traverse(
+        &self,
+        ray: &Ray,
+        min_t: f64,
+        max_t: f64,
+        mut hit: impl FnMut(usize, f64) -> Option<f64>,
+    ) {
+        self.walk(ray, min_t, max_t, |index, max_t| match hit(index, max_t) {
+            Some(t) => Walk::Shrink(t),
+            None => Walk::Continue,
+        });
+    }
+
+    /// Whether `hit` returns true for any primitive whose box `ray` passes
+    /// through, stopping at the first one.
+    pub fn any(
+        &self,
+        ray: &Ray,
+        min_t: f64,
+        max_t: f64,
+        mut hit: impl FnMut(usize) -> bool,
+    ) -> bool {
+        let mut found = false;
+        self.walk(ray, min_t, max_t, |index, _| {
+            if hit(index) {
+                found = true;
+                Walk::Stop
+            } else {
+                Walk::Continue
+            }
+        });
+        found
+    }
+
+    fn walk(
+        &self,
+        ray: &Ray,
+        min_t: f64,
+        mut max_t: f64,
+        mut visit: impl FnMut(usize, f64) -> Walk,
+    ) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let d = &ray.direction;
+        let inverse_direction = Vec3::new(1.0 / d.x, 1.0 / d.y, 1.0 / d.z);
+
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            match &self.nodes[node] {
+                Node::Leaf {
+                    bounds,
+                    first,
+                    count,
+                } => {
+                    if bounds
+                        .intersect(ray, &inverse_direction, min_t, max_t)
+                        .is_none()
+                    {
+                        continue;
+                    }
+                    for &index in &self.indices[*first..first + count] {
+                        match visit(index, max_t) {
+                            Walk::Continue => {}
+                            Walk::Shrink(t) => max_t = t,
+                            Walk::Stop => return,
+                        }
+                    }
+                }
+                Node::Interior { left, right, .. } => {
+                    let near_left =
+                        self.nodes[*left]
+                            .bounds()
+                            .intersect(ray, &inverse_direction, min_t, max_t);
+                    let near_right = self.nodes[*right].bounds().intersect(
+                        ray,
+                        &inverse_direction,
+                        min_t,
+                        max_t,
+                    );
+                    // Push the farther child first so the nearer one is visited first
+                    match (near_left, near_right) {
+                        (Some(l), Some(r)) if l <= r => stack.extend_from_slice(&[*right, *left]),
+                        (Some(_), Some(_)) => stack.extend_from_slice(&[*left, *right]),
+                        (Some(_), None) => stack.push(*left),
+                        (None, Some(_)) => stack.push(*right),
+                        (None, None) => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+enum Walk {
+    Continue,
+    Shrink(f64),
+    Stop,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube(center: Vec3, half: f64) -> Aabb {
+        let half = Vec3::new(half, half, half);
+        Aabb::new(center - half, center + half)
+    }
+
+    fn enter(aabb: &Aabb, origin: Vec3, direction: Vec3, max_t: f64) -> Option<f64> {
+        let ray = Ray::new(origin, direction);
+        let inverse = Vec3::new(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z);
+        aabb.intersect(&ray, &inverse, 0.0, max_t)
+    }
+
+    #[test]
+    fn slab_test_finds_where_rays_enter() {
+        let unit = cube(Vec3::ZERO, 1.0);
+        let z = Vec3::new(0.0, 0.0, 1.0);
+        assert_eq!(enter(&unit, Vec3::new(0.0, 0.0, -5.0), z, 10.0), Some(4.0));
+        assert_eq!(enter(&unit, Vec3::new(0.0, 0.0, -5.0), z, 3.0), None);
+        assert_eq!(enter(&unit, Vec3::new(0.0, 0.0, 5.0), z, 10.0), None);
+        // Starting inside enters at once
+        assert_eq!(enter(&unit, Vec3::ZERO, z, 10.0), Some(0.0));
+
+        let diagonal = Vec3::new(1.0, 1.0, 1.0);
+        assert_eq!(
+            enter(&unit, Vec3::new(-3.0, -3.0, -3.0), diagonal, 10.0),
+            Some(2.0)
+        );
+        assert_eq!(
+            enter(&unit, Vec3::new(-3.0, -3.0, 0.0), diagonal, 10.0),
+            None
+        );
+    }
+
+    #[test]
+    fn slab_test_handles_axis_parallel_rays() {
+        let unit = cube(Vec3::ZERO, 1.0);
+        let z = Vec3::new(0.0, 0.0, 1.0);
+        assert_eq!(enter(&unit, Vec3::new(0.5, -0.5, -5.0), z, 10.0), Some(4.0));
+        assert_eq!(enter(&unit, Vec3::new(2.0, 0.0, -5.0), z, 10.0), None);
+        assert_eq!(enter(&unit, Vec3::new(0.0, -1.5, -5.0), z, 10.0), None);
+        // Running along a face still counts as a hit
+        assert_eq!(enter(&unit, Vec3::new(1.0, 0.0, -5.0), z, 10.0), Some(4.0));
+        assert_eq!(enter(&unit, Vec3::new(-1.0, 1.0, -5.0), z, 10.0), Some(4.0));
+        // And a flat box is still hit head on
+        let flat = Aabb::new(Vec3::new(-1.0, -1.0, 0.0), Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(enter(&flat, Vec3::new(0.0, 0.0, -5.0), z, 10.0), Some(5.0));
+    }
+
+    fn visited(bvh: &Bvh, ray: &Ray) -> Vec<usize> {
+        let mut indices = Vec::new();
+        bvh.traverse(ray, 0.0, f64::INFINITY, |index, _| {
+            indices.push(index);
+            None
+        });
+        indices.sort_unstable();
+        indices
+    }
+
+    #[test]
+    fn every_primitive_lands_in_exactly_one_leaf() {
+        let bounds: Vec<Aabb> = (0..100)
+            .map(|i| cube(Vec3::new(i as f64 * 3.0, (i % 7) as f64, 0.0), 0.5))
+            .collect();
+        let bvh = Bvh::build(&bounds);
+        let mut indices = bvh.indices.clone();
+        indices.sort_unstable();
+        assert_eq!(indices, (0..100).collect::<Vec<_>>());
+        assert!(bvh.nodes.len() > 1);
+        assert!(bvh.nodes.iter().all(|node| match node {
+            Node::Leaf { count, .. } => *count <= MAX_FORCED_LEAF_SIZE,
+            Node::Interior { .. } => true,
+        }));
+
+        // Leaves are visited whole, so a ray down the x axis sees at least
+        // the boxes on it, and one above them all sees none
+        let ray = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let found = visited(&bvh, &ray);
+        assert!((0..100).filter(|i| i % 7 == 0).all(|i| found.contains(&i)));
+        assert!(bvh.any(&ray, 0.0, f64::INFINITY, |i| i == 14));
+        let above = Ray::new(Vec3::new(-5.0, 10.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(visited(&bvh, &above).is_empty());
+        assert!(!bvh.any(&above, 0.0, f64::INFINITY, |_| true));
+    }
+
+    #[test]
+    fn coinciding_centroids_still_build() {
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+
+        // Few enough for one leaf
+        let few = vec![cube(Vec3::ZERO, 1.0); MAX_FORCED_LEAF_SIZE];
+        let bvh = Bvh::build(&few);
+        assert_eq!(bvh.nodes.len(), 1);
+        assert_eq!(visited(&bvh, &ray).len(), MAX_FORCED_LEAF_SIZE);
+
+        // Too many, so they are halved with no split plane to go by. Boxes of
+        // different sizes around the same center give SAH nothing either
+        let many: Vec<Aabb> = (0..100).map(|i| cube(Vec3::ZERO, 1.0 + i as f64)).collect();
+        let bvh = Bvh::build(&many);
+        assert!(bvh.nodes.len() > 1);
+        assert_eq!(visited(&bvh, &ray), (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn empty_hierarchies_have_no_bounds_and_no_hits() {
+        let bvh = Bvh::build(&[]);
+        assert_eq!(bvh.bounds(), None);
+        let ray = Ray::new(Vec3::ZERO, Vec3::new(0.0, 0.0, 1.0));
+        assert!(visited(&bvh, &ray).is_empty());
+    }
+}
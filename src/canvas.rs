@@ -0,0 +1,410 @@
+use minifb::{Key, ScaleMode, Window, WindowOptions};
+
+/// Common interface of everything the renderer can draw into.
+///
+/// Coordinates are centered: `(0, 0)` is the middle of the image, `x` grows
+/// to the right and `y` grows upwards.
+pub trait Framebuffer {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+    fn clear_canvas(&mut self, color: &Rgb);
+    fn put_pixel(&mut self, x: i32, y: i32, color: &Rgb);
+}
+
+/// A framebuffer that only lives in memory and needs no windowing system.
+#[derive(Clone, Debug)]
+pub struct OffscreenCanvas {
+    buffer: Vec<u32>,
+    width: usize,
+    height: usize,
+}
+
+impl OffscreenCanvas {
+    pub fn new(width: usize, height: usize) -> Self {
+        OffscreenCanvas {
+            buffer: vec![0; width * height],
+            width,
+            height,
+        }
+    }
+
+    /// Pixels packed as `0x00RRGGBB`, row by row starting at the top left.
+    pub fn buffer(&self) -> &[u32] {
+        &self.buffer
+    }
+}
+
+impl Framebuffer for OffscreenCanvas {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn clear_canvas(&mut self, color: &Rgb) {
+        let col = pack(color);
+
+        for pixel in self.buffer.iter_mut() {
+            *pixel = col;
+        }
+    }
+
+    fn put_pixel(&mut self, x: i32, y: i32, color: &Rgb) {
+        if let Some(i) = buffer_index(self.width, self.height, x, y) {
+            self.buffer[i] = pack(color);
+        }
+    }
+}
+
+/// Maps centered canvas coordinates to an index into a row-major buffer.
+fn buffer_index(width: usize, height: usize, x: i32, y: i32) -> Option<usize> {
+    // Wide enough that coordinates far off the canvas can't overflow
+    let (width, height, x, y) = (width as i64, height as i64, x as i64, y as i64);
+
+    let screen_x = width / 2 + x;
+    let screen_y = height / 2 - y - 1;
+
+    if (screen_x < 0) | (screen_x >= width) | (screen_y < 0) | (screen_y >= height) {
+        return None;
+    }
+
+    Some((screen_x + width * screen_y) as usize)
+}
+
+/// Packs a color as `0x00RRGGBB`, clamping every channel to `0..=255` first.
+fn pack(color: &Rgb) -> u32 {
+    let color = color.clamp();
+    (color.red as u32) * 65536 + (color.green as u32) * 256 + (color.blue as u32)
+}
+
+/// How linear radiance is turned into 8-bit display values.
+///
+/// Radiance uses the same scale as [`Rgb`]: `255.0` is the brightest value a
+/// display can show without tone mapping.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quantization {
+    /// Multiplier applied before tone mapping.
+    pub exposure: f64,
+    pub tone_map: ToneMap,
+    /// Display gamma, `1.0` keeps the values linear.
+    pub gamma: f64,
+}
+
+impl Default for Quantization {
+    fn default() -> Self {
+        Quantization {
+            exposure: 1.0,
+            tone_map: ToneMap::Clamp,
+            gamma: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToneMap {
+    /// Cuts off everything above white.
+    Clamp,
+    /// `x / (1 + x)`, compresses highlights instead of clipping them.
+    Reinhard,
+}
+
+impl Quantization {
+    pub fn apply(&self, color: &Rgb) -> Rgb {
+        let map = |channel: f64| {
+            let mut x = f64::max(0.0, channel / 255.0 * self.exposure);
+            if self.tone_map == ToneMap::Reinhard {
+                x /= 1.0 + x;
+            }
+            (x.min(1.0).powf(1.0 / self.gamma) * 255.0).round()
+        };
+
+        Rgb {
+            red: map(color.red),
+            green: map(color.green),
+            blue: map(color.blue),
+        }
+    }
+}
+
+/// A framebuffer keeping unclamped, linear floating point radiance.
+///
+/// Nothing is lost until [`HdrCanvas::quantize`] converts it for display.
+#[derive(Clone, Debug)]
+pub struct HdrCanvas {
+    buffer: Vec<Rgb>,
+    width: usize,
+    height: usize,
+}
+
+impl HdrCanvas {
+    pub fn new(width: usize, height: usize) -> Self {
+        HdrCanvas {
+            buffer: vec![Rgb::from_ints(0, 0, 0); width * height],
+            width,
+            height,
+        }
+    }
+
+    /// Raw radiance, row by row starting at the top left.
+    pub fn buffer(&self) -> &[Rgb] {
+        &self.buffer
+    }
+
+    pub fn quantize(&self, quantization: &Quantization) -> OffscreenCanvas {
+        OffscreenCanvas {
+            buffer: self
+                .buffer
+                .iter()
+                .map(|color| pack(&quantization.apply(color)))
+                .collect(),
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+impl Framebuffer for HdrCanvas {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn clear_canvas(&mut self, color: &Rgb) {
+        for pixel in self.buffer.iter_mut() {
+            *pixel = *color;
+        }
+    }
+
+    fn put_pixel(&mut self, x: i32, y: i32, color: &Rgb) {
+        if let Some(i) = buffer_index(self.width, self.height, x, y) {
+            self.buffer[i] = *color;
+        }
+    }
+}
+
+/// An on-screen framebuffer backed by a minifb window.
+#[derive(Debug)]
+pub struct Canvas {
+    window: Window,
+    pixels: OffscreenCanvas,
+}
+
+impl Canvas {
+    pub fn new(name: &str, width: usize, height: usize) -> Self {
+        Self::try_new(name, width, height).expect("Window creation failed")
+    }
+
+    /// Like [`Canvas::new`], but reports a missing display instead of panicking.
+    pub fn try_new(name: &str, width: usize, height: usize) -> Result<Self, minifb::Error> {
+        Self::with_pixels(name, OffscreenCanvas::new(width, height))
+    }
+
+    /// Opens a window showing an already rendered offscreen canvas.
+    pub fn with_pixels(name: &str, pixels: OffscreenCanvas) -> Result<Self, minifb::Error> {
+        let mut window = Window::new(
+            name,
+            pixels.width,
+            pixels.height,
+            WindowOptions {
+                resize: false,
+                scale_mode: ScaleMode::UpperLeft,
+                ..WindowOptions::default()
+            },
+        )?;
+
+        window.limit_update_rate(Some(std::time::Duration::from_micros(24_000)));
+
+        Ok(Canvas { window, pixels })
+    }
+
+    pub fn display_until_exit(&mut self) {
+        // The unwrap causes the code to exit if the update fails
+        while self.window.is_open() && !self.window.is_key_down(Key::Escape) {
+            self.window
+                .update_with_buffer(&self.pixels.buffer, self.pixels.width, self.pixels.height)
+                .unwrap();
+        }
+    }
+}
+
+impl Framebuffer for Canvas {
+    fn width(&self) -> usize {
+        self.pixels.width()
+    }
+
+    fn height(&self) -> usize {
+        self.pixels.height()
+    }
+
+    fn clear_canvas(&mut self, color: &Rgb) {
+        self.pixels.clear_canvas(color)
+    }
+
+    fn put_pixel(&mut self, x: i32, y: i32, color: &Rgb) {
+        self.pixels.put_pixel(x, y, color)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rgb {
+    pub red: f64,
+    pub green: f64,
+    pub blue: f64,
+}
+
+impl Rgb {
+    pub fn multiply_by(&self, m: f64) -> Rgb {
+        Self {
+            red: self.red * m,
+            green: self.green * m,
+            blue: self.blue * m,
+        }
+    }
+
+    pub fn add(&self, a: &Rgb) -> Rgb {
+        Self {
+            red: self.red + a.red,
+            green: self.green + a.green,
+            blue: self.blue + a.blue,
+        }
+    }
+
+    /// Scales every channel by the matching channel of `filter`, where `255`
+    /// lets it through unchanged.
+    pub fn tint(&self, filter: &Rgb) -> Rgb {
+        Self {
+            red: self.red * filter.red / 255.0,
+            green: self.green * filter.green / 255.0,
+            blue: self.blue * filter.blue / 255.0,
+        }
+    }
+
+    /// Limits every channel to `0..=255`. NaN channels become `0`, which
+    /// `f64::clamp` would pass through.
+    #[rustfmt::skip]
+    #[allow(clippy::manual_clamp)]
+    pub fn clamp(&self) -> Rgb {
+        Rgb {
+            red:   f64::min(255.0, f64::max(0.0, self.red)),
+            green: f64::min(255.0, f64::max(0.0, self.green)),
+            blue:  f64::min(255.0, f64::max(0.0, self.blue)),
+        }
+    }
+
+    pub fn from_ints(red: i16, green: i16, blue: i16) -> Rgb {
+        Rgb {
+            red: red as f64,
+            green: green as f64,
+            blue: blue as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgb = Rgb {
+        red: 255.0,
+        green: 0.0,
+        blue: 0.0,
+    };
+
+    /// Index of the only pixel of `canvas` that isn't black.
+    fn lit_pixel(canvas: &OffscreenCanvas) -> Option<usize> {
+        let lit: Vec<usize> = (0..canvas.buffer().len())
+            .filter(|&i| canvas.buffer()[i] != 0)
+            .collect();
+        assert!(lit.len() <= 1, "more than one pixel lit: {:?}", lit);
+        lit.first().copied()
+    }
+
+    #[test]
+    fn put_pixel_maps_centered_coordinates() {
+        // On 4 by 2 pixels x runs from -2 to 1, and y from 0 on the top row
+        // to -1 on the bottom one
+        let cases = [
+            ((0, 0), 2),
+            ((-2, 0), 0),
+            ((1, 0), 3),
+            ((-2, -1), 4),
+            ((1, -1), 7),
+        ];
+        for &((x, y), index) in &cases {
+            let mut canvas = OffscreenCanvas::new(4, 2);
+            canvas.put_pixel(x, y, &RED);
+            assert_eq!(lit_pixel(&canvas), Some(index), "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn out_of_range_writes_are_ignored() {
+        for &(x, y) in &[(2, 0), (-3, 0), (0, 1), (0, -2), (i32::MAX, i32::MIN)] {
+            let mut canvas = OffscreenCanvas::new(4, 2);
+            canvas.put_pixel(x, y, &RED);
+            assert_eq!(lit_pixel(&canvas), None, "({}, {})", x, y);
+
+            let mut hdr = HdrCanvas::new(4, 2);
+            hdr.put_pixel(x, y, &RED);
+            assert!(hdr.buffer().iter().all(|pixel| *pixel == Rgb::default()));
+        }
+    }
+
+    #[test]
+    fn clear_canvas_fills_every_pixel() {
+        let mut canvas = OffscreenCanvas::new(3, 2);
+        canvas.put_pixel(0, 0, &RED);
+        canvas.clear_canvas(&Rgb::from_ints(1, 2, 3));
+        assert_eq!(canvas.buffer(), &[0x010203; 6]);
+
+        let mut hdr = HdrCanvas::new(3, 2);
+        hdr.clear_canvas(&Rgb::from_ints(1, 2, 3));
+        assert_eq!(hdr.buffer(), &[Rgb::from_ints(1, 2, 3); 6]);
+    }
+
+    #[test]
+    fn pixels_are_packed_and_clamped() {
+        let mut canvas = OffscreenCanvas::new(2, 2);
+        canvas.put_pixel(0, 0, &Rgb::from_ints(300, -5, 128));
+        assert_eq!(canvas.buffer()[1], 0xff0080);
+    }
+
+    #[test]
+    fn hdr_canvases_keep_radiance_until_quantized() {
+        let mut hdr = HdrCanvas::new(1, 1);
+        hdr.clear_canvas(&Rgb::from_ints(510, 255, 0));
+
+        let halved = Quantization {
+            exposure: 0.5,
+            ..Quantization::default()
+        };
+        assert_eq!(hdr.quantize(&halved).buffer(), &[0xff8000]);
+        assert_eq!(hdr.quantize(&Quantization::default()).buffer(), &[0xffff00]);
+    }
+
+    #[test]
+    fn tint_filters_each_channel() {
+        let color = Rgb::from_ints(200, 100, 50);
+        assert_eq!(color.tint(&Rgb::from_ints(255, 255, 255)), color);
+        assert_eq!(color.tint(&RED), Rgb::from_ints(200, 0, 0));
+        assert_eq!(
+            color.tint(&Rgb::from_ints(510, 0, 51)),
+            Rgb::from_ints(400, 0, 10)
+        );
+    }
+
+    #[test]
+    fn clamp_limits_channels_and_blacks_out_nan() {
+        let color = Rgb {
+            red: 300.0,
+            green: -1.0,
+            blue: f64::NAN,
+        };
+        assert_eq!(color.clamp(), Rgb::from_ints(255, 0, 0));
+    }
+}
@@ -0,0 +1,600 @@
+use cgfs::bvh::Acceleration;
+use cgfs::canvas::{Quantization, ToneMap};
+use cgfs::export::ImageFormat;
+use cgfs::raytracer::{Adaptive, RenderSettings};
+use cgfs::sampling::{Filter, SamplePattern};
+use cgfs::scene::Projection;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+Usage: cgfs [OPTIONS] [SCENE]
+
+Renders SCENE, a scene description file, or the built-in scene and shows it
+in a window.
+
+Options:
+  -r, --resolution WxH     Canvas size in pixels [default: 800x800]
+  -d, --depth N            Reflection and refraction depth [default: 3]
+  -s, --samples N          Samples per pixel [default: 1]
+  -j, --threads N          Render threads [default: one per CPU]
+  -o, --output FILE        Save to FILE instead of showing a window, as PPM,
+                           PNG, BMP, HDR or EXR depending on the extension
+      --no-window          Don't show a window, for timing renders
+  -h, --help               Print this help
+
+Sampling:
+      --pattern NAME       grid, jittered or random [default: grid]
+      --filter NAME        box, tent, gaussian or mitchell [default: box]
+      --adaptive THRESHOLD Refine pixels whose samples differ by more than
+                           THRESHOLD
+      --adaptive-depth N   How many times refined pixels are split
+
+Camera, overriding the scene's:
+      --projection NAME    perspective, orthographic, fisheye or
+                           equirectangular
+      --aperture RADIUS    Lens radius, 0 for a pinhole
+      --focus DISTANCE     Distance that is in focus
+
+Output:
+      --ascii              Write plain text PPM, for .ppm files only
+      --exposure MULTIPLIER
+                           Brighten or darken before tone mapping
+      --gamma GAMMA        Gamma correction
+      --tone-map NAME      clamp or reinhard
+      --brute-force        Test every object and triangle instead of using BVHs
+
+Non-square resolutions widen the viewport so pixels stay square, unless the
+scene sets the viewport's width or aspect.";
+
+pub struct Options {
+    pub scene: Option<PathBuf>,
+    /// Where to save the render, in the format its extension asks for.
+    pub output: Option<(PathBuf, ImageFormat)>,
+    pub window: bool,
+    pub width: usize,
+    pub height: usize,
+    pub quantization: Quantization,
+    pub settings: RenderSettings,
+    pub acceleration: Acceleration,
+    pub projection: Option<Projection>,
+    pub aperture: Option<f64>,
+    pub focus_distance: Option<f64>,
+}
+
+pub enum Command {
+    Render(Options),
+    Help,
+}
+
+fn value(flag: &str, value: Option<String>, expected: &str) -> Result<String, String> {
+    value.ok_or_else(|| format!("{} expects {}", flag, expected))
+}
+
+fn number_where(
+    flag: &str,
+    arg: Option<String>,
+    valid: fn(f64) -> bool,
+    expected: &str,
+) -> Result<f64, String> {
+    let arg = value(flag, arg, expected)?;
+    match arg.parse() {
+        Ok(n) if valid(n) => Ok(n),
+        _ => Err(format!("{} expects {}, found `{}`", flag, expected, arg)),
+    }
+}
+
+fn positive(flag: &str, arg: Option<String>, what: &str) -> Result<usize, String> {
+    let expected = format!("a positive number of {}", what);
+    let arg = value(flag, arg, &expected)?;
+    match arg.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("{} expects {}, found `{}`", flag, expected, arg)),
+    }
+}
+
+fn choice<T: Copy>(flag: &str, arg: Option<String>, choices: &[(&str, T)]) -> Result<T, String> {
+    let names: Vec<&str> = choices.iter().map(|&(name, _)| name).collect();
+    let expected = match names.split_last() {
+        Some((last, [])) => last.to_string(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => String::new(),
+    };
+    let arg = value(flag, arg, &expected)?;
+    match choices.iter().find(|&&(name, _)| name == arg) {
+        Some(&(_, choice)) => Ok(choice),
+        None => Err(format!("{} expects {}, found `{}`", flag, expected, arg)),
+    }
+}
+
+fn resolution(flag: &str, arg: Option<String>) -> Result<(usize, usize), String> {
+    let arg = value(flag, arg, "a size such as 800x600")?;
+    let size = arg
+        .split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)));
+    match size {
+        Some((width, height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(format!(
+            "{} expects a size such as 800x600, found `{}`",
+            flag, arg
+        )),
+    }
+}
+
+pub fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut options = Options {
+        scene: None,
+        output: None,
+        window: true,
+        width: 800,
+        height: 800,
+        quantization: Quantization::default(),
+        settings: RenderSettings::default(),
+        acceleration: Acceleration::Bvh,
+        projection: None,
+        aperture: None,
+        focus_distance: None,
+    };
+
+    let mut ascii = false;
+    while let Some(arg) = args.next() {
+        let flag = arg.as_str();
+        match flag {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-r" | "--resolution" => {
+                let (width, height) = resolution(flag, args.next())?;
+                options.width = width;
+                options.height = height;
+            }
+            "-d" | "--depth" => {
+                let arg = value(flag, args.next(), "a number of bounces")?;
+                match arg.parse::<u16>() {
+                    Ok(depth) => options.settings.depth = depth.into(),
+                    Err(_) => {
+                        return Err(format!(
+                            "{} expects a number of bounces, found `{}`",
+                            flag, arg
+                        ))
+                    }
+                }
+            }
+            "-s" | "--samples" => {
+                options.settings.samples = positive(flag, args.next(), "samples")?
+            }
+            "-j" | "--threads" => {
+                options.settings.threads = positive(flag, args.next(), "threads")?
+            }
+            "-o" | "--output" => {
+                let expected = "a .ppm, .png, .bmp, .hdr or .exr file";
+                let path = PathBuf::from(value(flag, args.next(), expected)?);
+                match ImageFormat::from_path(&path) {
+                    Some(format) => options.output = Some((path, format)),
+                    None => {
+                        return Err(format!(
+                            "{} expects {}, found `{}`",
+                            flag,
+                            expected,
+                            path.display()
+                        ))
+                    }
+                }
+            }
+            "--no-window" => options.window = false,
+            "--ascii" => ascii = true,
+            "--pattern" => {
+                options.settings.pattern = choice(
+                    flag,
+                    args.next(),
+                    &[
+                        ("grid", SamplePattern::Grid),
+                        ("jittered", SamplePattern::Jittered),
+                        ("random", SamplePattern::Random),
+                    ],
+                )?
+            }
+            "--filter" => {
+                options.settings.filter = choice(
+                    flag,
+                    args.next(),
+                    &[
+                        ("box", Filter::Box),
+                        ("tent", Filter::Tent),
+                        ("gaussian", Filter::Gaussian),
+                        ("mitchell", Filter::Mitchell),
+                    ],
+                )?
+            }
+            "--adaptive" => {
+                let threshold =
+                    number_where(flag, args.next(), |n| n >= 0.0, "a threshold of at least 0")?;
+                let adaptive = options
+                    .settings
+                    .adaptive
+                    .get_or_insert_with(Adaptive::default);
+                adaptive.threshold = threshold;
+            }
+            "--adaptive-depth" => {
+                let arg = value(flag, args.next(), "a number of subdivisions")?;
+                let max_depth = arg.parse().map_err(|_| {
+                    format!("{} expects a number of subdivisions, found `{}`", flag, arg)
+                })?;
+                let adaptive = options
+                    .settings
+                    .adaptive
+                    .get_or_insert_with(Adaptive::default);
+                adaptive.max_depth = max_depth;
+            }
+            "--projection" => {
+                options.projection = Some(choice(
+                    flag,
+                    args.next(),
+                    &[
+                        ("perspective", Projection::Perspective),
+                        ("orthographic", Projection::Orthographic),
+                        (
+                            "fisheye",
+                            Projection::Fisheye {
+                                fov: std::f64::consts::PI,
+                            },
+                        ),
+                        ("equirectangular", Projection::Equirectangular),
+                    ],
+                )?)
+            }
+            "--aperture" => {
+                options.aperture = Some(number_where(
+                    flag,
+                    args.next(),
+                    |n| n >= 0.0,
+                    "a radius of at least 0",
+                )?)
+            }
+            "--focus" => {
+                options.focus_distance = Some(number_where(
+                    flag,
+                    args.next(),
+                    |n| n > 0.0,
+                    "a positive distance",
+                )?)
+            }
+            "--brute-force" => options.acceleration = Acceleration::BruteForce,
+            "--exposure" => {
+                options.quantization.exposure = number_where(
+                    flag,
+                    args.next(),
+                    |n| n >= 0.0,
+                    "a multiplier of at least 0",
+                )?
+            }
+            "--gamma" => {
+                options.quantization.gamma =
+                    number_where(flag, args.next(), |n| n > 0.0, "a positive number")?
+            }
+            "--tone-map" => {
+                options.quantization.tone_map = choice(
+                    flag,
+                    args.next(),
+                    &[("clamp", ToneMap::Clamp), ("reinhard", ToneMap::Reinhard)],
+                )?
+            }
+            _ if flag.starts_with('-') => return Err(format!("unknown option `{}`", flag)),
+            _ if options.scene.is_some() => {
+                return Err(format!(
+                    "unexpected argument `{}`, only one scene can be rendered",
+                    flag
+                ))
+            }
+            _ => options.scene = Some(PathBuf::from(arg)),
+        }
+    }
+    if ascii {
+        match &mut options.output {
+            Some((_, format @ ImageFormat::Ppm)) => *format = ImageFormat::PpmAscii,
+            _ => return Err("--ascii only applies to .ppm output files".to_string()),
+        }
+    }
+    Ok(Command::Render(options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        match parse_args(args.iter().map(|arg| arg.to_string()))? {
+            Command::Render(options) => Ok(options),
+            Command::Help => panic!("expected options, got help"),
+        }
+    }
+
+    fn error(args: &[&str]) -> String {
+        match parse(args) {
+            Ok(_) => panic!("expected {:?} to be rejected", args),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn resolutions() {
+        let options = parse(&["-r", "640x480"]).unwrap();
+        assert_eq!((options.width, options.height), (640, 480));
+        let options = parse(&[]).unwrap();
+        assert_eq!((options.width, options.height), (800, 800));
+
+        assert_eq!(
+            error(&["-r", "0x5"]),
+            "-r expects a size such as 800x600, found `0x5`"
+        );
+        assert_eq!(
+            error(&["--resolution", "abc"]),
+            "--resolution expects a size such as 800x600, found `abc`"
+        );
+        assert_eq!(
+            error(&["--resolution"]),
+            "--resolution expects a size such as 800x600"
+        );
+    }
+
+    #[test]
+    fn exposure_and_gamma() {
+        let options = parse(&["--exposure", "0", "--gamma", "2.2"]).unwrap();
+        assert_eq!(options.quantization.exposure, 0.0);
+        assert_eq!(options.quantization.gamma, 2.2);
+
+        assert_eq!(
+            error(&["--exposure", "-1"]),
+            "--exposure expects a multiplier of at least 0, found `-1`"
+        );
+        assert_eq!(
+            error(&["--gamma", "0"]),
+            "--gamma expects a positive number, found `0`"
+        );
+        assert_eq!(
+            error(&["--gamma", "-2"]),
+            "--gamma expects a positive number, found `-2`"
+        );
+        assert_eq!(
+            error(&["--gamma", "NaN"]),
+            "--gamma expects a positive number, found `NaN`"
+        );
+        assert_eq!(error(&["--gamma"]), "--gamma expects a positive number");
+    }
+
+    #[test]
+    fn camera_overrides() {
+        let options = parse(&["--aperture", "0", "--focus", "2.5"]).unwrap();
+        assert_eq!(options.aperture, Some(0.0));
+        assert_eq!(options.focus_distance, Some(2.5));
+        let options = parse(&[]).unwrap();
+        assert_eq!((options.aperture, options.focus_distance), (None, None));
+        let options = parse(&["--projection", "fisheye"]).unwrap();
+        assert!(matches!(
+            options.projection,
+            Some(Projection::Fisheye { .. })
+        ));
+
+        let cases: &[(&[&str], &str)] = &[
+            (
+                &["--aperture", "-0.1"],
+                "--aperture expects a radius of at least 0, found `-0.1`",
+            ),
+            (
+                &["--aperture", "NaN"],
+                "--aperture expects a radius of at least 0, found `NaN`",
+            ),
+            (&["--aperture"], "--aperture expects a radius of at least 0"),
+            (
+                &["--focus", "0"],
+                "--focus expects a positive distance, found `0`",
+            ),
+            (
+                &["--focus", "-3"],
+                "--focus expects a positive distance, found `-3`",
+            ),
+            (
+                &["--focus", "far"],
+                "--focus expects a positive distance, found `far`",
+            ),
+            (
+                &["--projection", "fish"],
+                "--projection expects perspective, orthographic, fisheye or equirectangular, \
+                 found `fish`",
+            ),
+        ];
+        for (args, message) in cases {
+            assert_eq!(error(args), *message, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn render_settings() {
+        let options = parse(&[
+            "-d",
+            "5",
+            "-s",
+            "4",
+            "-j",
+            "2",
+            "--pattern",
+            "jittered",
+            "--filter",
+            "mitchell",
+        ])
+        .unwrap();
+        assert_eq!(options.settings.depth, 5);
+        assert_eq!(options.settings.samples, 4);
+        assert_eq!(options.settings.threads, 2);
+        assert_eq!(options.settings.pattern, SamplePattern::Jittered);
+        assert_eq!(options.settings.filter, Filter::Mitchell);
+        assert_eq!(options.settings.adaptive, None);
+        assert_eq!(parse(&["--depth", "0"]).unwrap().settings.depth, 0);
+
+        let cases: &[(&[&str], &str)] = &[
+            (
+                &["--depth", "deep"],
+                "--depth expects a number of bounces, found `deep`",
+            ),
+            (
+                &["--depth", "-1"],
+                "--depth expects a number of bounces, found `-1`",
+            ),
+            (
+                &["--depth", "65536"],
+                "--depth expects a number of bounces, found `65536`",
+            ),
+            (&["--depth"], "--depth expects a number of bounces"),
+            (
+                &["--samples", "0"],
+                "--samples expects a positive number of samples, found `0`",
+            ),
+            (&["-s"], "-s expects a positive number of samples"),
+            (
+                &["--threads", "0"],
+                "--threads expects a positive number of threads, found `0`",
+            ),
+            (
+                &["-j", "all"],
+                "-j expects a positive number of threads, found `all`",
+            ),
+            (
+                &["--pattern", "poisson"],
+                "--pattern expects grid, jittered or random, found `poisson`",
+            ),
+            (&["--pattern"], "--pattern expects grid, jittered or random"),
+            (
+                &["--filter", "lanczos"],
+                "--filter expects box, tent, gaussian or mitchell, found `lanczos`",
+            ),
+        ];
+        for (args, message) in cases {
+            assert_eq!(error(args), *message, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn adaptive_sampling() {
+        let adaptive = |args: &[&str]| parse(args).unwrap().settings.adaptive.unwrap();
+        let defaults = Adaptive::default();
+        assert_eq!(
+            adaptive(&["--adaptive", "0.1"]),
+            Adaptive {
+                threshold: 0.1,
+                ..defaults
+            }
+        );
+        assert_eq!(
+            adaptive(&["--adaptive-depth", "3"]),
+            Adaptive {
+                max_depth: 3,
+                ..defaults
+            }
+        );
+        assert_eq!(
+            adaptive(&["--adaptive-depth", "1", "--adaptive", "0"]),
+            Adaptive {
+                threshold: 0.0,
+                max_depth: 1
+            }
+        );
+
+        let cases: &[(&[&str], &str)] = &[
+            (
+                &["--adaptive", "-0.5"],
+                "--adaptive expects a threshold of at least 0, found `-0.5`",
+            ),
+            (
+                &["--adaptive", "nan"],
+                "--adaptive expects a threshold of at least 0, found `nan`",
+            ),
+            (
+                &["--adaptive"],
+                "--adaptive expects a threshold of at least 0",
+            ),
+            (
+                &["--adaptive-depth", "-1"],
+                "--adaptive-depth expects a number of subdivisions, found `-1`",
+            ),
+            (
+                &["--adaptive-depth"],
+                "--adaptive-depth expects a number of subdivisions",
+            ),
+        ];
+        for (args, message) in cases {
+            assert_eq!(error(args), *message, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn output_files_and_formats() {
+        let output = |args: &[&str]| parse(args).unwrap().output;
+        assert!(output(&[]).is_none());
+        assert_eq!(
+            output(&["-o", "out.png"]),
+            Some((PathBuf::from("out.png"), ImageFormat::Png))
+        );
+        assert_eq!(
+            output(&["--output", "render.PPM"]),
+            Some((PathBuf::from("render.PPM"), ImageFormat::Ppm))
+        );
+        assert_eq!(
+            output(&["--ascii", "-o", "out.ppm"]),
+            Some((PathBuf::from("out.ppm"), ImageFormat::PpmAscii))
+        );
+        assert!(parse(&[]).unwrap().window);
+        assert!(!parse(&["--no-window"]).unwrap().window);
+
+        let cases: &[(&[&str], &str)] = &[
+            (&["-o"], "-o expects a .ppm, .png, .bmp, .hdr or .exr file"),
+            (
+                &["-o", "out.jpg"],
+                "-o expects a .ppm, .png, .bmp, .hdr or .exr file, found `out.jpg`",
+            ),
+            (
+                &["--output", "render"],
+                "--output expects a .ppm, .png, .bmp, .hdr or .exr file, found `render`",
+            ),
+            (
+                &["--ascii", "-o", "out.png"],
+                "--ascii only applies to .ppm output files",
+            ),
+            (&["--ascii"], "--ascii only applies to .ppm output files"),
+            (
+                &["--tone-map", "aces"],
+                "--tone-map expects clamp or reinhard, found `aces`",
+            ),
+        ];
+        for (args, message) in cases {
+            assert_eq!(error(args), *message, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn scenes_help_and_unknown_options() {
+        assert_eq!(parse(&[]).unwrap().scene, None);
+        assert_eq!(
+            parse(&["scenes/basic.toml", "-d", "1"]).unwrap().scene,
+            Some(PathBuf::from("scenes/basic.toml"))
+        );
+        assert_eq!(
+            parse(&["--brute-force"]).unwrap().acceleration,
+            Acceleration::BruteForce
+        );
+        assert_eq!(parse(&[]).unwrap().acceleration, Acceleration::Bvh);
+
+        for &help in &["-h", "--help"] {
+            let args = vec!["-r".to_string(), "2x2".to_string(), help.to_string()];
+            assert!(matches!(parse_args(args.into_iter()), Ok(Command::Help)));
+        }
+
+        let cases: &[(&[&str], &str)] = &[
+            (
+                &["a.toml", "b.toml"],
+                "unexpected argument `b.toml`, only one scene can be rendered",
+            ),
+            (&["--verbose"], "unknown option `--verbose`"),
+            (&["-x", "a.toml"], "unknown option `-x`"),
+        ];
+        for (args, message) in cases {
+            assert_eq!(error(args), *message, "{:?}", args);
+        }
+    }
+}
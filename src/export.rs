@@ -0,0 +1,655 @@
+use crate::canvas::{Framebuffer, HdrCanvas, OffscreenCanvas, Quantization, Rgb};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// File formats a rendered canvas can be written as.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// Binary portable pixmap (`P6`).
+    Ppm,
+    /// ASCII portable pixmap (`P3`), handy for diffing renders as text.
+    PpmAscii,
+    Png,
+    Bmp,
+    /// Radiance RGBE, keeps the full floating point range.
+    Hdr,
+    /// OpenEXR with 32-bit float channels, keeps the full floating point range.
+    Exr,
+}
+
+impl ImageFormat {
+    /// Guesses the format from a file extension. `.ppm` is written as binary PPM.
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png),
+            "bmp" => Some(ImageFormat::Bmp),
+            "hdr" => Some(ImageFormat::Hdr),
+            "exr" => Some(ImageFormat::Exr),
+            _ => None,
+        }
+    }
+
+    /// Whether the format stores raw radiance rather than 8-bit display values.
+    pub fn is_high_dynamic_range(self) -> bool {
+        matches!(self, ImageFormat::Hdr | ImageFormat::Exr)
+    }
+}
+
+fn unsupported(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("unsupported image format: {}", path.display()),
+    )
+}
+
+/// Writes the canvas to `path`, picking the format from the file extension.
+pub fn save(canvas: &OffscreenCanvas, path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    let format = ImageFormat::from_path(path).ok_or_else(|| unsupported(path))?;
+    save_as(canvas, path, format)
+}
+
+pub fn save_as(
+    canvas: &OffscreenCanvas,
+    path: impl AsRef<Path>,
+    format: ImageFormat,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(&path)?);
+    match format {
+        ImageFormat::Ppm => write_ppm(canvas, &mut writer)?,
+        ImageFormat::PpmAscii => write_ppm_ascii(canvas, &mut writer)?,
+        ImageFormat::Png => write_png(canvas, &mut writer)?,
+        ImageFormat::Bmp => write_bmp(canvas, &mut writer)?,
+        ImageFormat::Hdr | ImageFormat::Exr => return Err(unsupported(path.as_ref())),
+    }
+    writer.flush()
+}
+
+/// Writes a float canvas to `path`, picking the format from the file extension.
+///
+/// `.hdr` and `.exr` receive the raw radiance, every other format is quantized
+/// with `quantization` first.
+pub fn save_hdr(
+    canvas: &HdrCanvas,
+    path: impl AsRef<Path>,
+    quantization: &Quantization,
+) -> io::Result<()> {
+    let path = path.as_ref();
+    let format = ImageFormat::from_path(path).ok_or_else(|| unsupported(path))?;
+    save_hdr_as(canvas, path, format, quantization)
+}
+
+pub fn save_hdr_as(
+    canvas: &HdrCanvas,
+    path: impl AsRef<Path>,
+    format: ImageFormat,
+    quantization: &Quantization,
+) -> io::Result<()> {
+    if !format.is_high_dynamic_range() {
+        return save_as(&canvas.quantize(quantization), path, format);
+    }
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    match format {
+        ImageFormat::Hdr => write_radiance_hdr(canvas, &mut writer)?,
+        _ => write_exr(canvas, &mut writer)?,
+    }
+    writer.flush()
+}
+
+fn channels(pixel: u32) -> [u8; 3] {
+    [(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8]
+}
+
+pub fn write_ppm<W: Write>(canvas: &OffscreenCanvas, w: &mut W) -> io::Result<()> {
+    write!(w, "P6\n{} {}\n255\n", canvas.width(), canvas.height())?;
+    for &pixel in canvas.buffer() {
+        w.write_all(&channels(pixel))?;
+    }
+    Ok(())
+}
+
+pub fn write_ppm_ascii<W: Write>(canvas: &OffscreenCanvas, w: &mut W) -> io::Result<()> {
+    write!(w, "P3\n{} {}\n255\n", canvas.width(), canvas.height())?;
+    for row in canvas.buffer().chunks(canvas.width().max(1)) {
+        let line: Vec<String> = row
+            .iter()
+            .map(|&pixel| {
+                let [r, g, b] = channels(pixel);
+                format!("{} {} {}", r, g, b)
+            })
+            .collect();
+        writeln!(w, "{}", line.join(" "))?;
+    }
+    Ok(())
+}
+
+/// 24-bit uncompressed BMP.
+pub fn write_bmp<W: Write>(canvas: &OffscreenCanvas, w: &mut W) -> io::Result<()> {
+    let (width, height) = (canvas.width(), canvas.height());
+    // Every row is padded to a multiple of four bytes
+    let row_size = (width * 3 + 3) & !3;
+    let image_size = row_size * height;
+    let header_size = 14 + 40;
+
+    // File header
+    w.write_all(b"BM")?;
+    w.write_all(&((header_size + image_size) as u32).to_le_bytes())?;
+    w.write_all(&0u32.to_le_bytes())?;
+    w.write_all(&(header_size as u32).to_le_bytes())?;
+
+    // BITMAPINFOHEADER
+    w.write_all(&40u32.to_le_bytes())?;
+    w.write_all(&(width as i32).to_le_bytes())?;
+    w.write_all(&(height as i32).to_le_bytes())?;
+    w.write_all(&1u16.to_le_bytes())?;
+    w.write_all(&24u16.to_le_bytes())?;
+    w.write_all(&0u32.to_le_bytes())?;
+    w.write_all(&(image_size as u32).to_le_bytes())?;
+    w.write_all(&2835i32.to_le_bytes())?;
+    w.write_all(&2835i32.to_le_bytes())?;
+    w.write_all(&0u32.to_le_bytes())?;
+    w.write_all(&0u32.to_le_bytes())?;
+
+    // Rows are stored bottom-up in BGR order
+    let mut row_bytes = vec![0u8; row_size];
+    for row in canvas.buffer().chunks(width.max(1)).rev() {
+        for (i, &pixel) in row.iter().enumerate() {
+            let [r, g, b] = channels(pixel);
+            row_bytes[i * 3..i * 3 + 3].copy_from_slice(&[b, g, r]);
+        }
+        w.write_all(&row_bytes)?;
+    }
+    Ok(())
+}
+
+/// 8-bit RGB PNG.
+///
+/// The image data is stored in uncompressed deflate blocks, so files are about
+/// as large as a binary PPM but open everywhere.
+pub fn write_png<W: Write>(canvas: &OffscreenCanvas, w: &mut W) -> io::Result<()> {
+    let (width, height) = (canvas.width(), canvas.height());
+
+    w.write_all(&[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'])?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // Bit depth 8, colour type 2 (RGB), deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_png_chunk(w, b"IHDR", &header)?;
+
+    let mut raw = Vec::with_capacity(height * (width * 3 + 1));
+    for row in canvas.buffer().chunks(width.max(1)) {
+        // Filter type 0 (none) for every scanline
+        raw.push(0);
+        for &pixel in row {
+            raw.extend_from_slice(&channels(pixel));
+        }
+    }
+    write_png_chunk(w, b"IDAT", &zlib_stored(&raw))?;
+    write_png_chunk(w, b"IEND", &[])
+}
+
+fn write_png_chunk<W: Write>(w: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    w.write_all(kind)?;
+    w.write_all(data)?;
+    let crc = crc32(crc32(0, kind), data);
+    w.write_all(&crc.to_be_bytes())
+}
+
+/// Wraps `data` in a zlib stream made of stored (uncompressed) deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 65_535;
+
+    let mut out = Vec::with_capacity(data.len() + data.len() / MAX_BLOCK * 5 + 11);
+    // CMF/FLG: deflate with a 32K window, no preset dictionary, fastest level
+    out.extend_from_slice(&[0x78, 0x01]);
+
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65_521;
+
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 is the largest run that cannot overflow before taking the modulus
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+    (b << 16) | a
+}
+
+/// Radiance in files is stored relative to white, so `255.0` becomes `1.0`.
+fn linear(color: &Rgb) -> [f32; 3] {
+    [
+        (color.red / 255.0) as f32,
+        (color.green / 255.0) as f32,
+        (color.blue / 255.0) as f32,
+    ]
+}
+
+/// Radiance `.hdr` with run length encoded RGBE scanlines.
+pub fn write_radiance_hdr<W: Write>(canvas: &HdrCanvas, w: &mut W) -> io::Result<()> {
+    let (width, height) = (canvas.width(), canvas.height());
+    write!(
+        w,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        height, width
+    )?;
+
+    let rgbe: Vec<[u8; 4]> = canvas.buffer().iter().map(|c| to_rgbe(linear(c))).collect();
+
+    // Scanline encoding only exists for widths in 8..=32767
+    if !(8..=0x7fff).contains(&width) {
+        for pixel in &rgbe {
+            w.write_all(pixel)?;
+        }
+        return Ok(());
+    }
+
+    let mut component = Vec::with_capacity(width);
+    for row in rgbe.chunks(width) {
+        w.write_all(&[2, 2, (width >> 8) as u8, width as u8])?;
+        // Each component is stored separately, as literal runs of at most 128 bytes
+        for c in 0..4 {
+            component.clear();
+            component.extend(row.iter().map(|pixel| pixel[c]));
+            for run in component.chunks(128) {
+                w.write_all(&[run.len() as u8])?;
+                w.write_all(run)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn to_rgbe([r, g, b]: [f32; 3]) -> [u8; 4] {
+    let (r, g, b) = (r.max(0.0), g.max(0.0), b.max(0.0));
+    let max = r.max(g).max(b);
+    if max < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+
+    // max = mantissa * 2^exponent with mantissa in [0.5, 1)
+    let exponent = max.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2f32.powi(exponent);
+    let channel = |v: f32| (v * scale).min(255.0) as u8;
+    [
+        channel(r),
+        channel(g),
+        channel(b),
+        (exponent + 128).clamp(0, 255) as u8,
+    ]
+}
+
+/// Uncompressed scanline OpenEXR with 32-bit float `B`, `G` and `R` channels.
+pub fn write_exr<W: Write>(canvas: &HdrCanvas, w: &mut W) -> io::Result<()> {
+    const FLOAT: i32 = 2;
+
+    let (width, height) = (canvas.width(), canvas.height());
+    let (max_x, max_y) = (width as i32 - 1, height as i32 - 1);
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&0x0131_2f76u32.to_le_bytes());
+    header.extend_from_slice(&2u32.to_le_bytes());
+
+    // Channels have to be listed, and stored, in alphabetical order
+    let mut channels = Vec::new();
+    for name in [b"B", b"G", b"R"] {
+        channels.extend_from_slice(name);
+        channels.push(0);
+        channels.extend_from_slice(&FLOAT.to_le_bytes());
+        channels.extend_from_slice(&[0, 0, 0, 0]);
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+    exr_attribute(&mut header, "channels", "chlist", &channels);
+
+    exr_attribute(&mut header, "compression", "compression", &[0]);
+
+    let mut window = Vec::new();
+    for v in [0, 0, max_x, max_y] {
+        window.extend_from_slice(&v.to_le_bytes());
+    }
+    exr_attribute(&mut header, "dataWindow", "box2i", &window);
+    exr_attribute(&mut header, "displayWindow", "box2i", &window);
+    exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    exr_attribute(
+        &mut header,
+        "pixelAspectRatio",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    exr_attribute(
+        &mut header,
+        "screenWindowWidth",
+        "float",
+        &1f32.to_le_bytes(),
+    );
+    header.push(0);
+    w.write_all(&header)?;
+
+    // Offset table, one entry per scanline block
+    let block_size = 8 + width * 3 * 4;
+    let first_block = header.len() + height * 8;
+    for y in 0..height {
+        w.write_all(&((first_block + y * block_size) as u64).to_le_bytes())?;
+    }
+
+    let mut block = Vec::with_capacity(block_size);
+    for (y, row) in canvas.buffer().chunks(width.max(1)).enumerate() {
+        block.clear();
+        block.extend_from_slice(&(y as i32).to_le_bytes());
+        block.extend_from_slice(&((width * 3 * 4) as i32).to_le_bytes());
+        for c in [2, 1, 0] {
+            for color in row {
+                block.extend_from_slice(&linear(color)[c].to_le_bytes());
+            }
+        }
+        w.write_all(&block)?;
+    }
+    Ok(())
+}
+
+fn exr_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    /// Red, green on top, blue, white below.
+    fn two_by_two() -> OffscreenCanvas {
+        let mut canvas = OffscreenCanvas::new(2, 2);
+        canvas.put_pixel(-1, 0, &Rgb::from_ints(255, 0, 0));
+        canvas.put_pixel(0, 0, &Rgb::from_ints(0, 255, 0));
+        canvas.put_pixel(-1, -1, &Rgb::from_ints(0, 0, 255));
+        canvas.put_pixel(0, -1, &Rgb::from_ints(255, 255, 255));
+        canvas
+    }
+
+    fn encode(write: fn(&OffscreenCanvas, &mut Vec<u8>) -> io::Result<()>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write(&two_by_two(), &mut bytes).unwrap();
+        bytes
+    }
+
+    fn u32_le(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn u32_be(bytes: &[u8], at: usize) -> u32 {
+        u32::from_be_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn ppm_is_a_header_and_rgb_rows_from_the_top() {
+        let mut expected = b"P6\n2 2\n255\n".to_vec();
+        expected.extend_from_slice(&[255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255]);
+        assert_eq!(encode(write_ppm), expected);
+    }
+
+    #[test]
+    fn ascii_ppm_has_one_line_per_row() {
+        let text = String::from_utf8(encode(write_ppm_ascii)).unwrap();
+        assert_eq!(text, "P3\n2 2\n255\n255 0 0 0 255 0\n0 0 255 255 255 255\n");
+    }
+
+    #[test]
+    fn bmp_rows_are_bgr_padded_and_bottom_up() {
+        let bytes = encode(write_bmp);
+        // Two rows of 6 pixel bytes padded to 8
+        assert_eq!(bytes.len(), 54 + 2 * 8);
+        assert_eq!(&bytes[0..2], b"BM");
+        assert_eq!(u32_le(&bytes, 2), 70);
+        assert_eq!(u32_le(&bytes, 10), 54);
+        assert_eq!(u32_le(&bytes, 14), 40);
+        assert_eq!(u32_le(&bytes, 18), 2);
+        assert_eq!(u32_le(&bytes, 22), 2);
+        assert_eq!(u32_le(&bytes, 34), 16);
+        assert_eq!(
+            &bytes[54..],
+            &[
+                255, 0, 0, 255, 255, 255, 0, 0, // blue, white
+                0, 0, 255, 0, 255, 0, 0, 0, // red, green
+            ]
+        );
+    }
+
+    #[test]
+    fn png_chunks_checksums_and_image_data_are_valid() {
+        let bytes = encode(write_png);
+        assert_eq!(
+            &bytes[..8],
+            &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n']
+        );
+
+        let mut chunks = Vec::new();
+        let mut at = 8;
+        while at < bytes.len() {
+            let len = u32_be(&bytes, at) as usize;
+            let kind = &bytes[at + 4..at + 8];
+            let data = &bytes[at + 8..at + 8 + len];
+            let crc = u32_be(&bytes, at + 8 + len);
+            assert_eq!(crc, crc32(crc32(0, kind), data), "bad CRC");
+            chunks.push((kind.to_vec(), data.to_vec()));
+            at += 12 + len;
+        }
+        assert_eq!(at, bytes.len());
+        let kinds: Vec<&[u8]> = chunks.iter().map(|(kind, _)| &kind[..]).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 2, 0, 0, 0, 2, 8, 2, 0, 0, 0]);
+
+        // zlib header, one final stored block, then the Adler-32 of the data
+        let idat = &chunks[1].1;
+        assert_eq!(&idat[..2], &[0x78, 0x01]);
+        assert_eq!((idat[0] as u16 * 256 + idat[1] as u16) % 31, 0);
+        assert_eq!(idat[2], 1);
+        let len = u16::from_le_bytes([idat[3], idat[4]]);
+        assert_eq!(!len, u16::from_le_bytes([idat[5], idat[6]]));
+        let raw = &idat[7..7 + len as usize];
+        assert_eq!(raw, &[0, 255, 0, 0, 0, 255, 0, 0, 0, 0, 255, 255, 255, 255]);
+        assert_eq!(u32_be(idat, 7 + len as usize), adler32(raw));
+        assert_eq!(idat.len(), 7 + len as usize + 4);
+    }
+
+    #[test]
+    fn zlib_splits_large_data_into_stored_blocks() {
+        let data = vec![7u8; 70_000];
+        let stream = zlib_stored(&data);
+        assert_eq!(stream.len(), 2 + 5 + 65_535 + 5 + 4_465 + 4);
+        assert_eq!(stream[2], 0);
+        assert_eq!(stream[2 + 5 + 65_535], 1);
+        assert_eq!(
+            zlib_stored(&[]),
+            [0x78, 0x01, 1, 0, 0, 0xff, 0xff, 0, 0, 0, 1]
+        );
+    }
+
+    #[test]
+    fn crc32_matches_known_values() {
+        assert_eq!(crc32(0, b""), 0);
+        assert_eq!(crc32(0, b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xcbf4_3926);
+        assert_eq!(crc32(0, b"IEND"), 0xae42_6082);
+    }
+
+    #[test]
+    fn adler32_matches_known_values() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+
+        // Long runs of large bytes, where the modulus matters
+        let data = vec![0xff; 100_000];
+        let (mut a, mut b) = (1u64, 0u64);
+        for &byte in &data {
+            a = (a + byte as u64) % 65_521;
+            b = (b + a) % 65_521;
+        }
+        assert_eq!(adler32(&data), ((b << 16) | a) as u32);
+    }
+
+    fn hdr_two_by_two() -> HdrCanvas {
+        let mut canvas = HdrCanvas::new(2, 2);
+        canvas.put_pixel(-1, 0, &Rgb::from_ints(255, 0, 0));
+        canvas.put_pixel(0, 0, &Rgb::from_ints(0, 510, 0));
+        canvas.put_pixel(-1, -1, &Rgb::from_ints(0, 0, 0));
+        canvas.put_pixel(0, -1, &Rgb::from_ints(64, 64, 64));
+        canvas
+    }
+
+    fn from_rgbe([r, g, b, e]: [u8; 4]) -> [f32; 3] {
+        if e == 0 {
+            return [0.0; 3];
+        }
+        let scale = 2f32.powi(e as i32 - 128 - 8);
+        [r, g, b].map(|c| (c as f32 + 0.5) * scale)
+    }
+
+    #[test]
+    fn rgbe_edge_values() {
+        assert_eq!(to_rgbe([0.0, 0.0, 0.0]), [0, 0, 0, 0]);
+        assert_eq!(to_rgbe([1e-40, 0.0, 0.0]), [0, 0, 0, 0]);
+        assert_eq!(to_rgbe([-1.0, -2.0, -3.0]), [0, 0, 0, 0]);
+        assert_eq!(to_rgbe([1.0, 1.0, 1.0]), [128, 128, 128, 129]);
+        assert_eq!(to_rgbe([0.5, 0.25, 0.0]), [128, 64, 0, 128]);
+        // Just below a power of two the mantissa is almost 1
+        assert_eq!(to_rgbe([0.999, 0.0, 0.0]), [255, 0, 0, 128]);
+        assert_eq!(to_rgbe([-1.0, 2.0, 0.0]), [0, 128, 0, 130]);
+    }
+
+    #[test]
+    fn rgbe_round_trips_within_the_mantissa_precision() {
+        for &v in &[1e-6f32, 0.01, 0.3, 1.0, 7.5, 1000.0, 1e30] {
+            let [r, g, b] = from_rgbe(to_rgbe([v, v / 2.0, v / 4.0]));
+            assert!((r - v).abs() <= v / 128.0, "{} became {}", v, r);
+            assert!((g - v / 2.0).abs() <= v / 128.0);
+            assert!((b - v / 4.0).abs() <= v / 128.0);
+        }
+    }
+
+    #[test]
+    fn radiance_hdr_has_a_header_and_flat_pixels_for_narrow_images() {
+        let mut bytes = Vec::new();
+        write_radiance_hdr(&hdr_two_by_two(), &mut bytes).unwrap();
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 2\n";
+        assert_eq!(&bytes[..header.len()], &header[..]);
+        assert_eq!(
+            &bytes[header.len()..],
+            &[128, 0, 0, 129, 0, 128, 0, 130, 0, 0, 0, 0, 128, 128, 128, 127]
+        );
+    }
+
+    #[test]
+    fn radiance_hdr_stores_wide_scanlines_by_component() {
+        let mut canvas = HdrCanvas::new(8, 1);
+        canvas.clear_canvas(&Rgb::from_ints(255, 255, 255));
+        let mut bytes = Vec::new();
+        write_radiance_hdr(&canvas, &mut bytes).unwrap();
+        let pixels = &bytes[bytes.len() - 4 - 4 * 9..];
+        assert_eq!(&pixels[..4], &[2, 2, 0, 8]);
+        for (c, run) in pixels[4..].chunks(9).enumerate() {
+            let value = if c == 3 { 129 } else { 128 };
+            assert_eq!(run[0], 8);
+            assert!(run[1..].iter().all(|&v| v == value));
+        }
+    }
+
+    #[test]
+    fn exr_has_magic_header_and_offset_table() {
+        let mut bytes = Vec::new();
+        write_exr(&hdr_two_by_two(), &mut bytes).unwrap();
+        assert_eq!(&bytes[..4], &[0x76, 0x2f, 0x31, 0x01]);
+        assert_eq!(u32_le(&bytes, 4), 2);
+
+        // Walk the attributes, name, type, size and value, up to the empty name
+        let mut names = Vec::new();
+        let mut at = 8;
+        while bytes[at] != 0 {
+            let field = |at: usize| {
+                let end = at + bytes[at..].iter().position(|&b| b == 0).unwrap();
+                (String::from_utf8(bytes[at..end].to_vec()).unwrap(), end + 1)
+            };
+            let (name, next) = field(at);
+            let (_, next) = field(next);
+            let size = u32_le(&bytes, next) as usize;
+            names.push(name);
+            at = next + 4 + size;
+        }
+        let header_end = at + 1;
+        for name in &[
+            "channels",
+            "compression",
+            "dataWindow",
+            "displayWindow",
+            "lineOrder",
+        ] {
+            assert!(names.iter().any(|n| n == name), "missing {}", name);
+        }
+
+        let block_size = 8 + 2 * 3 * 4;
+        let first = header_end + 2 * 8;
+        let offset = |y: usize| {
+            u64::from_le_bytes(bytes[header_end + y * 8..][..8].try_into().unwrap()) as usize
+        };
+        assert_eq!(offset(0), first);
+        assert_eq!(offset(1), first + block_size);
+        assert_eq!(bytes.len(), first + 2 * block_size);
+
+        // The second scanline, channels B, G, R for each pixel
+        let block = &bytes[offset(1)..];
+        assert_eq!(u32_le(block, 0), 1);
+        assert_eq!(u32_le(block, 4), 24);
+        let floats: Vec<f32> = block[8..32]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect();
+        let quarter = 64.0 / 255.0;
+        assert_eq!(floats, [0.0, quarter, 0.0, quarter, 0.0, quarter]);
+    }
+}
@@ -0,0 +1,855 @@
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Vec3 {
+    pub const ZERO: Vec3 = Vec3::new(0.0, 0.0, 0.0);
+
+    pub const fn new(x: f64, y: f64, z: f64) -> Vec3 {
+        Vec3 { x, y, z }
+    }
+
+    pub fn dot(&self, b: &Vec3) -> f64 {
+        self.x * b.x + self.y * b.y + self.z * b.z
+    }
+
+    pub fn cross(&self, b: &Vec3) -> Vec3 {
+        Vec3 {
+            x: self.y * b.z - self.z * b.y,
+            y: self.z * b.x - self.x * b.z,
+            z: self.x * b.y - self.y * b.x,
+        }
+    }
+
+    pub fn length_squared(&self) -> f64 {
+        self.dot(self)
+    }
+
+    pub fn length(&self) -> f64 {
+        self.length_squared().sqrt()
+    }
+
+    /// The unit vector pointing the same way, or zero for the zero vector.
+    pub fn normalize(&self) -> Vec3 {
+        let length = self.length();
+        if length == 0.0 {
+            return Vec3::ZERO;
+        }
+        *self / length
+    }
+
+    /// Linear interpolation, `self` at `t = 0` and `b` at `t = 1`.
+    pub fn lerp(&self, b: &Vec3, t: f64) -> Vec3 {
+        *self + (*b - *self) * t
+    }
+
+    /// Component-wise product.
+    pub fn mul_elements(&self, b: &Vec3) -> Vec3 {
+        Vec3::new(self.x * b.x, self.y * b.y, self.z * b.z)
+    }
+
+    /// Two unit vectors perpendicular to this unit vector and to each other.
+    pub fn orthonormal_basis(&self) -> (Vec3, Vec3) {
+        // Duff et al., "Building an Orthonormal Basis, Revisited"
+        let sign = 1.0_f64.copysign(self.z);
+        let a = -1.0 / (sign + self.z);
+        let b = self.x * self.y * a;
+        (
+            Vec3::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+            Vec3::new(b, sign + self.y * self.y * a, -self.y),
+        )
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+
+    fn add(self, b: Vec3) -> Vec3 {
+        Vec3::new(self.x + b.x, self.y + b.y, self.z + b.z)
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+
+    fn sub(self, b: Vec3) -> Vec3 {
+        Vec3::new(self.x - b.x, self.y - b.y, self.z - b.z)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Vec3;
+
+    fn neg(self) -> Vec3 {
+        Vec3::new(-self.x, -self.y, -self.z)
+    }
+}
+
+impl Mul<f64> for Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, k: f64) -> Vec3 {
+        Vec3::new(self.x * k, self.y * k, self.z * k)
+    }
+}
+
+impl Mul<Vec3> for f64 {
+    type Output = Vec3;
+
+    fn mul(self, v: Vec3) -> Vec3 {
+        v * self
+    }
+}
+
+impl Div<f64> for Vec3 {
+    type Output = Vec3;
+
+    fn div(self, k: f64) -> Vec3 {
+        Vec3::new(self.x / k, self.y / k, self.z / k)
+    }
+}
+
+impl AddAssign for Vec3 {
+    fn add_assign(&mut self, b: Vec3) {
+        *self = *self + b;
+    }
+}
+
+impl SubAssign for Vec3 {
+    fn sub_assign(&mut self, b: Vec3) {
+        *self = *self - b;
+    }
+}
+
+impl MulAssign<f64> for Vec3 {
+    fn mul_assign(&mut self, k: f64) {
+        *self = *self * k;
+    }
+}
+
+impl DivAssign<f64> for Vec3 {
+    fn div_assign(&mut self, k: f64) {
+        *self = *self / k;
+    }
+}
+
+/// The method names the raytracer used before the operators existed.
+impl Vec3 {
+    #[deprecated(note = "use `Vec3::dot`")]
+    pub fn dot_product(&self, b: &Vec3) -> f64 {
+        self.dot(b)
+    }
+
+    #[deprecated(note = "use `+`")]
+    pub fn add(&self, b: &Vec3) -> Vec3 {
+        *self + *b
+    }
+
+    #[deprecated(note = "use `-`")]
+    pub fn subtract(&self, b: &Vec3) -> Vec3 {
+        *self - *b
+    }
+
+    #[deprecated(note = "use `*`")]
+    pub fn multiply_by(&self, k: f64) -> Vec3 {
+        *self * k
+    }
+
+    #[deprecated(note = "use `/`")]
+    pub fn divide_by(&self, k: f64) -> Vec3 {
+        *self / k
+    }
+
+    #[deprecated(note = "use `mat * v`")]
+    pub fn mat_lmul(&self, mat: &Mat3) -> Vec3 {
+        *mat * *self
+    }
+}
+
+#[deprecated(note = "renamed to `Mat3`")]
+pub type Matrix3x3 = Mat3;
+
+/// Whether a determinant is too small to invert the matrix with `fields`.
+///
+/// Hadamard's inequality bounds the determinant by the product of the row
+/// lengths, comparing against that bound keeps uniformly scaled matrices such
+/// as a scale by `0.001` invertible.
+fn is_singular<const N: usize>(fields: &[[f64; N]; N], det: f64) -> bool {
+    let bound: f64 = fields
+        .iter()
+        .map(|row| row.iter().map(|x| x * x).sum::<f64>().sqrt())
+        .product();
+    det.abs() <= bound * f64::EPSILON
+}
+
+/// A row-major 3x3 matrix, multiplied with column vectors as `m * v`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat3 {
+    pub fields: [[f64; 3]; 3],
+}
+
+impl Mat3 {
+    #[allow(clippy::too_many_arguments)]
+    #[rustfmt::skip]
+    pub fn new(
+        a11: f64, a12: f64, a13: f64,
+        a21: f64, a22: f64, a23: f64,
+        a31: f64, a32: f64, a33: f64,
+    ) -> Mat3 {
+        Mat3 {
+            fields: [[a11, a12, a13], [a21, a22, a23], [a31, a32, a33]],
+        }
+    }
+
+    #[rustfmt::skip]
+    pub fn identity() -> Mat3 {
+        Mat3::new(
+            1.0, 0.0, 0.0,
+            0.0, 1.0, 0.0,
+            0.0, 0.0, 1.0,
+        )
+    }
+
+    /// The matrix whose columns are `x`, `y` and `z`, i.e. the one mapping the
+    /// standard axes onto them.
+    #[rustfmt::skip]
+    pub fn from_columns(x: Vec3, y: Vec3, z: Vec3) -> Mat3 {
+        Mat3::new(
+            x.x, y.x, z.x,
+            x.y, y.y, z.y,
+            x.z, y.z, z.z,
+        )
+    }
+
+    pub fn column(&self, i: usize) -> Vec3 {
+        Vec3::new(self.fields[0][i], self.fields[1][i], self.fields[2][i])
+    }
+
+    #[rustfmt::skip]
+    pub fn scale(s: Vec3) -> Mat3 {
+        Mat3::new(
+            s.x, 0.0, 0.0,
+            0.0, s.y, 0.0,
+            0.0, 0.0, s.z,
+        )
+    }
+
+    /// Rotation by `angle` radians around the x axis, turning y towards z.
+    #[rustfmt::skip]
+    pub fn rotation_x(angle: f64) -> Mat3 {
+        let (s, c) = angle.sin_cos();
+        Mat3::new(
+            1.0, 0.0, 0.0,
+            0.0, c,   -s,
+            0.0, s,   c,
+        )
+    }
+
+    /// Rotation by `angle` radians around the y axis, turning z towards x.
+    #[rustfmt::skip]
+    pub fn rotation_y(angle: f64) -> Mat3 {
+        let (s, c) = angle.sin_cos();
+        Mat3::new(
+            c,   0.0, s,
+            0.0, 1.0, 0.0,
+            -s,  0.0, c,
+        )
+    }
+
+    /// Rotation by `angle` radians around the z axis, turning x towards y.
+    #[rustfmt::skip]
+    pub fn rotation_z(angle: f64) -> Mat3 {
+        let (s, c) = angle.sin_cos();
+        Mat3::new(
+            c,   -s,  0.0,
+            s,   c,   0.0,
+            0.0, 0.0, 1.0,
+        )
+    }
+
+    /// Rotation by `angle` radians around `axis`, in the same sense as
+    /// [`Mat3::rotation_x`] and friends. `axis` does not need to be normalized.
+    #[rustfmt::skip]
+    pub fn from_axis_angle(axis: &Vec3, angle: f64) -> Mat3 {
+        let Vec3 { x, y, z } = axis.normalize();
+        let (s, c) = angle.sin_cos();
+        let t = 1.0 - c;
+        Mat3::new(
+            t * x * x + c,     t * x * y - s * z, t * x * z + s * y,
+            t * x * y + s * z, t * y * y + c,     t * y * z - s * x,
+            t * x * z - s * y, t * y * z + s * x, t * z * z + c,
+        )
+    }
+
+    /// Rotation around x, then y, then z, all angles in radians.
+    pub fn from_euler(x: f64, y: f64, z: f64) -> Mat3 {
+        Mat3::rotation_z(z) * Mat3::rotation_y(y) * Mat3::rotation_x(x)
+    }
+
+    pub fn transpose(&self) -> Mat3 {
+        let mut fields = [[0.0; 3]; 3];
+        for (i, row) in fields.iter_mut().enumerate() {
+            for (j, field) in row.iter_mut().enumerate() {
+                *field = self.fields[j][i];
+            }
+        }
+        Mat3 { fields }
+    }
+
+    pub fn determinant(&self) -> f64 {
+        let m = &self.fields;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    /// `None` if the matrix is singular.
+    pub fn inverse(&self) -> Option<Mat3> {
+        let det = self.determinant();
+        if is_singular(&self.fields, det) {
+            return None;
+        }
+
+        let m = &self.fields;
+        let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| {
+            m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+        };
+        // Transposed cofactor matrix divided by the determinant
+        #[rustfmt::skip]
+        let inverse = Mat3::new(
+            cofactor(1, 2, 1, 2),  -cofactor(0, 2, 1, 2), cofactor(0, 1, 1, 2),
+            -cofactor(1, 2, 0, 2), cofactor(0, 2, 0, 2),  -cofactor(0, 1, 0, 2),
+            cofactor(1, 2, 0, 1),  -cofactor(0, 2, 0, 1), cofactor(0, 1, 0, 1),
+        );
+        Some(inverse * (1.0 / det))
+    }
+}
+
+impl Mul for Mat3 {
+    type Output = Mat3;
+
+    fn mul(self, b: Mat3) -> Mat3 {
+        let mut fields = [[0.0; 3]; 3];
+        for (i, row) in fields.iter_mut().enumerate() {
+            for (j, field) in row.iter_mut().enumerate() {
+                *field = (0..3).map(|k| self.fields[i][k] * b.fields[k][j]).sum();
+            }
+        }
+        Mat3 { fields }
+    }
+}
+
+impl Mul<Vec3> for Mat3 {
+    type Output = Vec3;
+
+    #[rustfmt::skip]
+    fn mul(self, v: Vec3) -> Vec3 {
+        let m = &self.fields;
+        Vec3 {
+            x: m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            y: m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            z: m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        }
+    }
+}
+
+impl Mul<f64> for Mat3 {
+    type Output = Mat3;
+
+    fn mul(mut self, k: f64) -> Mat3 {
+        for field in self.fields.iter_mut().flatten() {
+            *field *= k;
+        }
+        self
+    }
+}
+
+/// A row-major 4x4 homogeneous transform, multiplied with column vectors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4 {
+    pub fields: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn identity() -> Mat4 {
+        Mat4::from_mat3(&Mat3::identity())
+    }
+
+    /// Embeds a linear transform, without translation.
+    pub fn from_mat3(m: &Mat3) -> Mat4 {
+        let mut fields = [[0.0; 4]; 4];
+        for (row, m_row) in fields.iter_mut().zip(m.fields.iter()) {
+            row[..3].copy_from_slice(m_row);
+        }
+        fields[3][3] = 1.0;
+        Mat4 { fields }
+    }
+
+    pub fn translation(t: Vec3) -> Mat4 {
+        let mut m = Mat4::identity();
+        m.fields[0][3] = t.x;
+        m.fields[1][3] = t.y;
+        m.fields[2][3] = t.z;
+        m
+    }
+
+    pub fn scale(s: Vec3) -> Mat4 {
+        Mat4::from_mat3(&Mat3::scale(s))
+    }
+
+    /// See [`Mat3::from_axis_angle`].
+    pub fn from_axis_angle(axis: &Vec3, angle: f64) -> Mat4 {
+        Mat4::from_mat3(&Mat3::from_axis_angle(axis, angle))
+    }
+
+    /// See [`Mat3::from_euler`].
+    pub fn from_euler(x: f64, y: f64, z: f64) -> Mat4 {
+        Mat4::from_mat3(&Mat3::from_euler(x, y, z))
+    }
+
+    /// The upper left 3x3 block, i.e. the transform without translation.
+    pub fn linear(&self) -> Mat3 {
+        let mut fields = [[0.0; 3]; 3];
+        for (i, row) in fields.iter_mut().enumerate() {
+            row.copy_from_slice(&self.fields[i][..3]);
+        }
+        Mat3 { fields }
+    }
+
+    /// Transforms a position, translation and perspective divide included.
+    pub fn transform_point(&self, p: &Vec3) -> Vec3 {
+        let [x, y, z, w] = self.apply([p.x, p.y, p.z, 1.0]);
+        Vec3::new(x / w, y / w, z / w)
+    }
+
+    /// Transforms a direction, ignoring translation.
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let [x, y, z, _] = self.apply([v.x, v.y, v.z, 0.0]);
+        Vec3::new(x, y, z)
+    }
+
+    fn apply(&self, v: [f64; 4]) -> [f64; 4] {
+        let mut out = [0.0; 4];
+        for (i, o) in out.iter_mut().enumerate() {
+            *o = (0..4).map(|k| self.fields[i][k] * v[k]).sum();
+        }
+        out
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut fields = [[0.0; 4]; 4];
+        for (i, row) in fields.iter_mut().enumerate() {
+            for (j, field) in row.iter_mut().enumerate() {
+                *field = self.fields[j][i];
+            }
+        }
+        Mat4 { fields }
+    }
+
+    pub fn determinant(&self) -> f64 {
+        // Laplace expansion along the first row
+        (0..4)
+            .map(|j| {
+                let sign = if j % 2 == 0 { 1.0 } else { -1.0 };
+                sign * self.fields[0][j] * self.minor(0, j)
+            })
+            .sum()
+    }
+
+    /// Determinant of the 3x3 matrix left after removing `row` and `column`.
+    fn minor(&self, row: usize, column: usize) -> f64 {
+        let mut fields = [[0.0; 3]; 3];
+        let rows = (0..4).filter(|&i| i != row);
+        for (sub_row, i) in fields.iter_mut().zip(rows) {
+            let columns = (0..4).filter(|&j| j != column);
+            for (field, j) in sub_row.iter_mut().zip(columns) {
+                *field = self.fields[i][j];
+            }
+        }
+        Mat3 { fields }.determinant()
+    }
+
+    /// `None` if the matrix is singular.
+    pub fn inverse(&self) -> Option<Mat4> {
+        let det = self.determinant();
+        if is_singular(&self.fields, det) {
+            return None;
+        }
+
+        // Transposed cofactor matrix divided by the determinant
+        let mut fields = [[0.0; 4]; 4];
+        for (i, row) in fields.iter_mut().enumerate() {
+            for (j, field) in row.iter_mut().enumerate() {
+                let sign = if (i + j) % 2 == 0 { 1.0 } else { -1.0 };
+                *field = sign * self.minor(j, i) / det;
+            }
+        }
+        Some(Mat4 { fields })
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, b: Mat4) -> Mat4 {
+        let mut fields = [[0.0; 4]; 4];
+        for (i, row) in fields.iter_mut().enumerate() {
+            for (j, field) in row.iter_mut().enumerate() {
+                *field = (0..4).map(|k| self.fields[i][k] * b.fields[k][j]).sum();
+            }
+        }
+        Mat4 { fields }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::FRAC_PI_2;
+
+    const EPS: f64 = 1e-9;
+
+    fn assert_vec_eq(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < EPS, "{:?} != {:?}", a, b);
+    }
+
+    fn assert_mat3_eq(a: &Mat3, b: &Mat3) {
+        for (x, y) in a.fields.iter().flatten().zip(b.fields.iter().flatten()) {
+            assert!((x - y).abs() < EPS, "{:?} != {:?}", a, b);
+        }
+    }
+
+    fn assert_mat4_eq(a: &Mat4, b: &Mat4) {
+        for (x, y) in a.fields.iter().flatten().zip(b.fields.iter().flatten()) {
+            assert!((x - y).abs() < EPS, "{:?} != {:?}", a, b);
+        }
+    }
+
+    fn assert_orthonormal_basis(n: Vec3) {
+        let (t, b) = n.orthonormal_basis();
+        for v in &[t, b] {
+            assert!((v.length() - 1.0).abs() < EPS, "{:?} has {:?}", n, v);
+            assert!(v.dot(&n).abs() < EPS, "{:?} has {:?}", n, v);
+        }
+        assert!(t.dot(&b).abs() < EPS, "{:?}", n);
+        // Right-handed, t x b = n
+        assert_vec_eq(t.cross(&b), n);
+    }
+
+    #[rustfmt::skip]
+    fn sample_mat3() -> Mat3 {
+        Mat3::new(
+            2.0, -1.0, 0.0,
+            1.0, 3.0,  2.0,
+            0.0, 1.0,  4.0,
+        )
+    }
+
+    fn sample_mat4() -> Mat4 {
+        Mat4::translation(Vec3::new(1.0, -2.0, 3.0))
+            * Mat4::from_axis_angle(&Vec3::new(1.0, 1.0, 0.0), 0.7)
+            * Mat4::scale(Vec3::new(2.0, 3.0, 0.5))
+    }
+
+    #[test]
+    fn vec3_arithmetic() {
+        let a = Vec3::new(1.0, 2.0, 3.0);
+        let b = Vec3::new(4.0, -5.0, 6.0);
+        assert_eq!(a + b, Vec3::new(5.0, -3.0, 9.0));
+        assert_eq!(a - b, Vec3::new(-3.0, 7.0, -3.0));
+        assert_eq!(-a, Vec3::new(-1.0, -2.0, -3.0));
+        assert_eq!(a * 2.0, Vec3::new(2.0, 4.0, 6.0));
+        assert_eq!(2.0 * a, Vec3::new(2.0, 4.0, 6.0));
+        assert_eq!(b / 2.0, Vec3::new(2.0, -2.5, 3.0));
+        assert_eq!(a.mul_elements(&b), Vec3::new(4.0, -10.0, 18.0));
+    }
+
+    #[test]
+    fn vec3_assign_operators() {
+        let mut v = Vec3::new(1.0, 2.0, 3.0);
+        v += Vec3::new(1.0, 1.0, 1.0);
+        assert_eq!(v, Vec3::new(2.0, 3.0, 4.0));
+        v -= Vec3::new(2.0, 2.0, 2.0);
+        assert_eq!(v, Vec3::new(0.0, 1.0, 2.0));
+        v *= 3.0;
+        assert_eq!(v, Vec3::new(0.0, 3.0, 6.0));
+        v /= 3.0;
+        assert_eq!(v, Vec3::new(0.0, 1.0, 2.0));
+    }
+
+    #[test]
+    fn vec3_products() {
+        let a = Vec3::new(1.0, 2.0, 3.0);
+        let b = Vec3::new(4.0, -5.0, 6.0);
+        assert_eq!(a.dot(&b), 12.0);
+
+        let x = Vec3::new(1.0, 0.0, 0.0);
+        let y = Vec3::new(0.0, 1.0, 0.0);
+        assert_eq!(x.cross(&y), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(y.cross(&x), Vec3::new(0.0, 0.0, -1.0));
+        let c = a.cross(&b);
+        assert!(c.dot(&a).abs() < EPS && c.dot(&b).abs() < EPS);
+    }
+
+    #[test]
+    fn vec3_length_normalize_lerp() {
+        let v = Vec3::new(3.0, 4.0, 0.0);
+        assert_eq!(v.length_squared(), 25.0);
+        assert_eq!(v.length(), 5.0);
+        assert_vec_eq(v.normalize(), Vec3::new(0.6, 0.8, 0.0));
+        assert_eq!(Vec3::ZERO.normalize(), Vec3::ZERO);
+
+        let w = Vec3::new(-1.0, 0.0, 2.0);
+        assert_eq!(v.lerp(&w, 0.0), v);
+        assert_eq!(v.lerp(&w, 1.0), w);
+        assert_vec_eq(v.lerp(&w, 0.5), Vec3::new(1.0, 2.0, 1.0));
+    }
+
+    #[test]
+    fn mat3_vector_multiplication() {
+        let v = Vec3::new(1.0, 2.0, 3.0);
+        assert_eq!(Mat3::identity() * v, v);
+        assert_eq!(sample_mat3() * v, Vec3::new(0.0, 13.0, 14.0));
+        assert_eq!(
+            Mat3::scale(Vec3::new(2.0, 3.0, 4.0)) * v,
+            Vec3::new(2.0, 6.0, 12.0)
+        );
+    }
+
+    #[test]
+    fn mat3_columns() {
+        let (x, y, z) = (
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(4.0, 5.0, 6.0),
+            Vec3::new(7.0, 8.0, 9.0),
+        );
+        let m = Mat3::from_columns(x, y, z);
+        assert_eq!(m.column(0), x);
+        assert_eq!(m.column(1), y);
+        assert_eq!(m.column(2), z);
+        assert_eq!(m * Vec3::new(0.0, 1.0, 0.0), y);
+    }
+
+    #[test]
+    fn mat3_composition() {
+        let a = sample_mat3();
+        let b = Mat3::rotation_y(0.3);
+        let v = Vec3::new(-1.0, 0.5, 2.0);
+        assert_vec_eq((a * b) * v, a * (b * v));
+        assert_mat3_eq(&(a * Mat3::identity()), &a);
+        assert_mat3_eq(&(Mat3::identity() * a), &a);
+    }
+
+    #[test]
+    fn mat3_scalar_multiplication() {
+        let m = Mat3::identity() * 2.0;
+        assert_eq!(m, Mat3::scale(Vec3::new(2.0, 2.0, 2.0)));
+    }
+
+    #[test]
+    fn mat3_transpose() {
+        let m = sample_mat3();
+        assert_eq!(m.transpose().fields[0], [2.0, 1.0, 0.0]);
+        assert_eq!(m.transpose().transpose(), m);
+    }
+
+    #[test]
+    fn mat3_determinant() {
+        assert_eq!(Mat3::identity().determinant(), 1.0);
+        assert_eq!(sample_mat3().determinant(), 24.0);
+        assert!((Mat3::from_euler(0.1, 0.2, 0.3).determinant() - 1.0).abs() < EPS);
+    }
+
+    #[test]
+    fn mat3_inverse() {
+        let m = sample_mat3();
+        let inverse = m.inverse().unwrap();
+        assert_mat3_eq(&(m * inverse), &Mat3::identity());
+        assert_mat3_eq(&(inverse * m), &Mat3::identity());
+
+        let singular = Mat3::scale(Vec3::new(1.0, 0.0, 1.0));
+        assert!(singular.inverse().is_none());
+    }
+
+    #[test]
+    fn mat3_inverse_is_independent_of_scale() {
+        let small = Mat3::scale(Vec3::new(0.001, 0.001, 0.001));
+        assert_mat3_eq(&small.inverse().unwrap(), &(Mat3::identity() * 1000.0));
+        let large = sample_mat3() * 1e6;
+        assert_mat3_eq(&(large * large.inverse().unwrap()), &Mat3::identity());
+
+        // Rows that are parallel up to rounding
+        #[rustfmt::skip]
+        let nearly_singular = Mat3::new(
+            1.0, 2.0, 3.0,
+            4.0, 5.0, 6.0,
+            7.0, 8.0, 9.0,
+        );
+        assert!(nearly_singular.inverse().is_none());
+        assert!((Mat3::identity() * 0.0).inverse().is_none());
+    }
+
+    #[test]
+    fn mat3_axis_rotations() {
+        let (x, y, z) = (
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        assert_vec_eq(Mat3::rotation_x(FRAC_PI_2) * y, z);
+        assert_vec_eq(Mat3::rotation_y(FRAC_PI_2) * z, x);
+        assert_vec_eq(Mat3::rotation_z(FRAC_PI_2) * x, y);
+
+        // Rotations are orthonormal, so the transpose undoes them
+        let r = Mat3::rotation_x(0.4);
+        assert_mat3_eq(&(r.transpose() * r), &Mat3::identity());
+    }
+
+    #[test]
+    fn mat3_axis_angle() {
+        assert_mat3_eq(
+            &Mat3::from_axis_angle(&Vec3::new(2.0, 0.0, 0.0), 0.5),
+            &Mat3::rotation_x(0.5),
+        );
+        assert_mat3_eq(
+            &Mat3::from_axis_angle(&Vec3::new(0.0, 1.0, 0.0), 0.5),
+            &Mat3::rotation_y(0.5),
+        );
+        assert_mat3_eq(
+            &Mat3::from_axis_angle(&Vec3::new(0.0, 0.0, 1.0), 0.5),
+            &Mat3::rotation_z(0.5),
+        );
+
+        // The axis itself is left untouched
+        let axis = Vec3::new(1.0, 2.0, -1.0);
+        assert_vec_eq(Mat3::from_axis_angle(&axis, 1.3) * axis, axis);
+    }
+
+    #[test]
+    fn mat3_euler() {
+        let expected = Mat3::rotation_z(0.3) * Mat3::rotation_y(0.2) * Mat3::rotation_x(0.1);
+        assert_mat3_eq(&Mat3::from_euler(0.1, 0.2, 0.3), &expected);
+        assert_mat3_eq(&Mat3::from_euler(0.0, 0.0, 0.0), &Mat3::identity());
+    }
+
+    #[test]
+    fn mat4_transforms() {
+        let p = Vec3::new(1.0, 2.0, 3.0);
+        let t = Mat4::translation(Vec3::new(1.0, 1.0, 1.0));
+        assert_eq!(t.transform_point(&p), Vec3::new(2.0, 3.0, 4.0));
+        assert_eq!(t.transform_vector(&p), p);
+
+        let s = Mat4::scale(Vec3::new(2.0, 2.0, 2.0));
+        assert_eq!(s.transform_point(&p), Vec3::new(2.0, 4.0, 6.0));
+
+        assert_vec_eq(
+            Mat4::from_axis_angle(&Vec3::new(0.0, 0.0, 1.0), FRAC_PI_2).transform_vector(&p),
+            Vec3::new(-2.0, 1.0, 3.0),
+        );
+        assert_mat3_eq(
+            &Mat4::from_euler(0.1, 0.2, 0.3).linear(),
+            &Mat3::from_euler(0.1, 0.2, 0.3),
+        );
+    }
+
+    #[test]
+    fn mat4_perspective_divide() {
+        let mut m = Mat4::identity();
+        m.fields[3] = [0.0, 0.0, 1.0, 0.0];
+        assert_eq!(
+            m.transform_point(&Vec3::new(2.0, 4.0, 2.0)),
+            Vec3::new(1.0, 2.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn mat4_composition() {
+        let a = Mat4::translation(Vec3::new(0.0, 5.0, 0.0));
+        let b = Mat4::scale(Vec3::new(2.0, 2.0, 2.0));
+        let p = Vec3::new(1.0, 1.0, 1.0);
+        // Scaling happens first, then the translation
+        assert_eq!((a * b).transform_point(&p), Vec3::new(2.0, 7.0, 2.0));
+        assert_eq!((b * a).transform_point(&p), Vec3::new(2.0, 12.0, 2.0));
+        assert_mat4_eq(&(sample_mat4() * Mat4::identity()), &sample_mat4());
+    }
+
+    #[test]
+    fn mat4_transpose() {
+        let m = sample_mat4();
+        assert_eq!(m.transpose().fields[3][0], m.fields[0][3]);
+        assert_eq!(m.transpose().transpose(), m);
+    }
+
+    #[test]
+    fn mat4_determinant() {
+        assert_eq!(Mat4::identity().determinant(), 1.0);
+        // Rotation and translation keep volume, the scale multiplies it by 3
+        assert!((sample_mat4().determinant() - 3.0).abs() < EPS);
+    }
+
+    #[test]
+    fn mat4_inverse() {
+        let m = sample_mat4();
+        let inverse = m.inverse().unwrap();
+        assert_mat4_eq(&(m * inverse), &Mat4::identity());
+        assert_mat4_eq(&(inverse * m), &Mat4::identity());
+
+        let p = Vec3::new(0.3, -4.0, 2.5);
+        assert_vec_eq(inverse.transform_point(&m.transform_point(&p)), p);
+
+        assert!(Mat4::scale(Vec3::new(0.0, 1.0, 1.0)).inverse().is_none());
+
+        let small = Mat4::scale(Vec3::new(0.001, 0.001, 0.001));
+        let p = Vec3::new(0.001, 0.002, -0.003);
+        assert_vec_eq(
+            small.inverse().unwrap().transform_point(&p),
+            Vec3::new(1.0, 2.0, -3.0),
+        );
+    }
+
+    #[test]
+    fn orthonormal_basis_of_axes() {
+        for &axis in &[
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        ] {
+            assert_orthonormal_basis(axis);
+        }
+    }
+
+    #[test]
+    fn orthonormal_basis_of_random_normals() {
+        let mut rng = crate::sampling::Rng::new(3);
+        for _ in 0..1000 {
+            let mut coordinate = || rng.next_f64() * 2.0 - 1.0;
+            let n = Vec3::new(coordinate(), coordinate(), coordinate()).normalize();
+            assert_orthonormal_basis(n);
+        }
+        // Close to -z, where the construction switches sign
+        assert_orthonormal_basis(Vec3::new(1e-9, 0.0, -1.0).normalize());
+        assert_orthonormal_basis(Vec3::new(0.0, 1e-9, -1.0).normalize());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn deprecated_names_match_the_operators() {
+        let a = Vec3::new(1.0, 2.0, 3.0);
+        let b = Vec3::new(4.0, -5.0, 6.0);
+        assert_eq!(a.dot_product(&b), a.dot(&b));
+        assert_eq!(Vec3::add(&a, &b), a + b);
+        assert_eq!(a.subtract(&b), a - b);
+        assert_eq!(a.multiply_by(2.0), a * 2.0);
+        assert_eq!(a.divide_by(2.0), a / 2.0);
+        let m: Matrix3x3 = sample_mat3();
+        assert_eq!(a.mat_lmul(&m), m * a);
+    }
+}
@@ -0,0 +1,513 @@
+use crate::bvh::{Aabb, Acceleration, Bvh};
+use crate::canvas::Rgb;
+use crate::math::Vec3;
+use crate::object::{Hit, Material, Object, Ray, WithMaterial};
+
+/// Möller–Trumbore ray/triangle intersection.
+///
+/// Returns `t` and the barycentric coordinates `(u, v)` of the hit, weighting
+/// `p1` and `p2` respectively. Triangles are hit from both sides.
+pub fn intersect_ray_triangle(
+    ray: &Ray,
+    [p0, p1, p2]: [&Vec3; 3],
+    min_t: f64,
+    max_t: f64,
+) -> Option<(f64, f64, f64)> {
+    let edge1 = *p1 - *p0;
+    let edge2 = *p2 - *p0;
+
+    let p = ray.direction.cross(&edge2);
+    let determinant = edge1.dot(&p);
+    // Relative to the edge and ray lengths, so tiny and huge meshes behave alike
+    let scale = edge1.length() * edge2.length() * ray.direction.length();
+    if determinant.abs() <= 1e-12 * scale {
+        return None;
+    }
+    let inverse = 1.0 / determinant;
+
+    let s = ray.origin - *p0;
+    let u = s.dot(&p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(&edge1);
+    let v = ray.direction.dot(&q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(&q) * inverse;
+    if t < min_t || t > max_t {
+        return None;
+    }
+    Some((t, u, v))
+}
+
+/// Picks the normal to shade with, facing the incoming ray, and whether the
+/// ray hit the front face.
+///
+/// `normals` are per-vertex normals that get interpolated for a smooth look,
+/// without them the triangle is shaded flat. The front face is the one the
+/// vertices wind counter-clockwise around.
+fn shading_normal(
+    ray: &Ray,
+    [p0, p1, p2]: [&Vec3; 3],
+    normals: Option<[&Vec3; 3]>,
+    u: f64,
+    v: f64,
+) -> (Vec3, bool) {
+    let geometric = (*p1 - *p0).cross(&(*p2 - *p0)).normalize();
+    let normal = match normals {
+        Some([n0, n1, n2]) => (*n0 * (1.0 - u - v) + *n1 * u + *n2 * v).normalize(),
+        None => geometric,
+    };
+
+    // Decide on the side with the geometric normal, interpolated normals can
+    // disagree with it near silhouettes
+    if geometric.dot(&ray.direction) > 0.0 {
+        (-normal, false)
+    } else {
+        (normal, true)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Triangle {
+    pub vertices: [Vec3; 3],
+    /// Optional per-vertex normals for smooth shading.
+    pub normals: Option<[Vec3; 3]>,
+    pub material: Material,
+}
+
+impl Triangle {
+    /// A flat shaded triangle.
+    pub fn new(p0: Vec3, p1: Vec3, p2: Vec3, color: Rgb) -> Triangle {
+        Triangle {
+            vertices: [p0, p1, p2],
+            normals: None,
+            material: Material::new(color),
+        }
+    }
+
+    pub fn with_normals(mut self, n0: Vec3, n1: Vec3, n2: Vec3) -> Triangle {
+        self.normals = Some([n0, n1, n2]);
+        self
+    }
+}
+
+impl WithMaterial for Triangle {
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+}
+
+impl Object for Triangle {
+    fn intersect(&self, ray: &Ray, min_t: f64, max_t: f64) -> Option<Hit<'_>> {
+        let [p0, p1, p2] = &self.vertices;
+        let (t, u, v) = intersect_ray_triangle(ray, [p0, p1, p2], min_t, max_t)?;
+        let normals = self.normals.as_ref().map(|[n0, n1, n2]| [n0, n1, n2]);
+        let (normal, front_face) = shading_normal(ray, [p0, p1, p2], normals, u, v);
+        Some(Hit {
+            t,
+            point: ray.at(t),
+            normal,
+            front_face,
+            material: &self.material,
+        })
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::from_points(&self.vertices).padded(1e-9))
+    }
+}
+
+/// An indexed triangle mesh sharing one material.
+///
+/// The geometry is only readable, as the mesh keeps a BVH over its triangles
+/// built when it is created.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mesh {
+    positions: Vec<Vec3>,
+    /// Per-vertex normals, either empty for flat shading or as long as
+    /// `positions`.
+    normals: Vec<Vec3>,
+    /// Per-vertex texture coordinates, either empty or as long as `positions`.
+    uvs: Vec<[f64; 2]>,
+    /// Indices into `positions` (and `normals` and `uvs`), three per triangle.
+    triangles: Vec<[usize; 3]>,
+    pub material: Material,
+    bvh: Bvh,
+    acceleration: Acceleration,
+}
+
+impl Mesh {
+    /// A flat shaded mesh.
+    ///
+    /// # Panics
+    ///
+    /// If a triangle refers to a vertex that doesn't exist.
+    pub fn new(positions: Vec<Vec3>, triangles: Vec<[usize; 3]>, color: Rgb) -> Mesh {
+        for triangle in &triangles {
+            for &i in triangle {
+                assert!(
+                    i < positions.len(),
+                    "triangle refers to vertex {} of {}",
+                    i,
+                    positions.len()
+                );
+            }
+        }
+
+        let bounds: Vec<Aabb> = triangles
+            .iter()
+            .map(|&[i0, i1, i2]| {
+                Aabb::from_points(&[positions[i0], positions[i1], positions[i2]]).padded(1e-9)
+            })
+            .collect();
+
+        Mesh {
+            bvh: Bvh::build(&bounds),
+            positions,
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            triangles,
+            material: Material::new(color),
+            acceleration: Acceleration::default(),
+        }
+    }
+
+    pub fn positions(&self) -> &[Vec3] {
+        &self.positions
+    }
+
+    pub fn normals(&self) -> &[Vec3] {
+        &self.normals
+    }
+
+    pub fn uvs(&self) -> &[[f64; 2]] {
+        &self.uvs
+    }
+
+    pub fn triangles(&self) -> &[[usize; 3]] {
+        &self.triangles
+    }
+
+    /// # Panics
+    ///
+    /// If there isn't exactly one normal per vertex.
+    pub fn with_normals(mut self, normals: Vec<Vec3>) -> Mesh {
+        assert_eq!(
+            normals.len(),
+            self.positions.len(),
+            "a mesh needs one normal per vertex"
+        );
+        self.normals = normals.iter().map(Vec3::normalize).collect();
+        self
+    }
+
+    /// # Panics
+    ///
+    /// If there isn't exactly one texture coordinate per vertex.
+    pub fn with_uvs(mut self, uvs: Vec<[f64; 2]>) -> Mesh {
+        assert_eq!(
+            uvs.len(),
+            self.positions.len(),
+            "a mesh needs one texture coordinate per vertex"
+        );
+        self.uvs = uvs;
+        self
+    }
+
+    /// Smooth shading with vertex normals averaged from the adjacent
+    /// triangles, weighted by their area.
+    pub fn with_smooth_normals(mut self) -> Mesh {
+        let mut normals = vec![Vec3::ZERO; self.positions.len()];
+        for &[i0, i1, i2] in &self.triangles {
+            let (p0, p1, p2) = (self.positions[i0], self.positions[i1], self.positions[i2]);
+            // The cross product's length is twice the area
+            let n = (p1 - p0).cross(&(p2 - p0));
+            for &i in &[i0, i1, i2] {
+                normals[i] += n;
+            }
+        }
+        self.normals = normals.iter().map(Vec3::normalize).collect();
+        self
+    }
+
+    /// Whether rays walk the mesh's BVH or test every triangle.
+    pub fn with_acceleration(mut self, acceleration: Acceleration) -> Mesh {
+        self.acceleration = acceleration;
+        self
+    }
+
+    fn vertices(&self, triangle: usize) -> [&Vec3; 3] {
+        let [i0, i1, i2] = self.triangles[triangle];
+        [
+            &self.positions[i0],
+            &self.positions[i1],
+            &self.positions[i2],
+        ]
+    }
+
+    /// `t`, barycentric coordinates and index of the closest triangle hit.
+    fn closest_triangle(
+        &self,
+        ray: &Ray,
+        min_t: f64,
+        max_t: f64,
+    ) -> Option<(f64, f64, f64, usize)> {
+        let mut closest = None;
+        let mut hit = |triangle, max_t| {
+            let (t, u, v) = intersect_ray_triangle(ray, self.vertices(triangle), min_t, max_t)?;
+            closest = Some((t, u, v, triangle));
+            Some(t)
+        };
+
+        match self.acceleration {
+            Acceleration::Bvh => self.bvh.traverse(ray, min_t, max_t, hit),
+            Acceleration::BruteForce => {
+                let mut max_t = max_t;
+                for triangle in 0..self.triangles.len() {
+                    if let Some(t) = hit(triangle, max_t) {
+                        max_t = t;
+                    }
+                }
+            }
+        }
+        closest
+    }
+}
+
+impl WithMaterial for Mesh {
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+}
+
+impl Object for Mesh {
+    fn intersect(&self, ray: &Ray, min_t: f64, max_t: f64) -> Option<Hit<'_>> {
+        let (t, u, v, triangle) = self.closest_triangle(ray, min_t, max_t)?;
+
+        let normals = if self.normals.is_empty() {
+            None
+        } else {
+            let [i0, i1, i2] = self.triangles[triangle];
+            Some([&self.normals[i0], &self.normals[i1], &self.normals[i2]])
+        };
+
+        let (normal, front_face) = shading_normal(ray, self.vertices(triangle), normals, u, v);
+        Some(Hit {
+            t,
+            point: ray.at(t),
+            normal,
+            front_face,
+            material: &self.material,
+        })
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.bvh.bounds()
+    }
+
+    fn occludes(&self, ray: &Ray, min_t: f64, max_t: f64) -> bool {
+        let hit =
+            |triangle| intersect_ray_triangle(ray, self.vertices(triangle), min_t, max_t).is_some();
+
+        match self.acceleration {
+            Acceleration::Bvh => self.bvh.any(ray, min_t, max_t, hit),
+            Acceleration::BruteForce => (0..self.triangles.len()).any(hit),
+        }
+    }
+
+    fn set_acceleration(&mut self, acceleration: Acceleration) {
+        self.acceleration = acceleration;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::Moving;
+
+    const EPS: f64 = 1e-9;
+
+    /// In the z = 0 plane, its front face looking towards +z.
+    fn triangle() -> Triangle {
+        Triangle::new(
+            Vec3::ZERO,
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Rgb::from_ints(128, 128, 128),
+        )
+    }
+
+    fn from_front(x: f64, y: f64) -> Ray {
+        Ray::new(Vec3::new(x, y, 5.0), Vec3::new(0.0, 0.0, -1.0))
+    }
+
+    fn from_back(x: f64, y: f64) -> Ray {
+        Ray::new(Vec3::new(x, y, -5.0), Vec3::new(0.0, 0.0, 1.0))
+    }
+
+    fn assert_vec_eq(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < EPS, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn triangle_is_hit_inside() {
+        let triangle = triangle();
+        let hit = triangle
+            .intersect(&from_front(0.25, 0.25), 0.0, f64::INFINITY)
+            .unwrap();
+        assert!((hit.t - 5.0).abs() < EPS);
+        assert_vec_eq(hit.point, Vec3::new(0.25, 0.25, 0.0));
+        assert_vec_eq(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!(hit.front_face);
+
+        let [p0, p1, p2] = &triangle.vertices;
+        let (_, u, v) =
+            intersect_ray_triangle(&from_front(0.25, 0.5), [p0, p1, p2], 0.0, f64::INFINITY)
+                .unwrap();
+        assert!((u - 0.25).abs() < EPS && (v - 0.5).abs() < EPS);
+    }
+
+    #[test]
+    fn triangle_edges_and_corners_are_hit() {
+        let triangle = triangle();
+        for &(x, y) in &[
+            (0.3, 0.0),
+            (0.0, 0.3),
+            (0.5, 0.5),
+            (0.0, 0.0),
+            (1.0, 0.0),
+            (0.0, 1.0),
+        ] {
+            assert!(
+                triangle
+                    .intersect(&from_front(x, y), 0.0, f64::INFINITY)
+                    .is_some(),
+                "({}, {}) missed",
+                x,
+                y
+            );
+        }
+        for &(x, y) in &[(0.3, -1e-6), (-1e-6, 0.3), (0.5 + 1e-6, 0.5), (1.1, 0.0)] {
+            assert!(
+                triangle
+                    .intersect(&from_front(x, y), 0.0, f64::INFINITY)
+                    .is_none(),
+                "({}, {}) hit",
+                x,
+                y
+            );
+        }
+    }
+
+    #[test]
+    fn triangle_back_face_is_hit_with_a_flipped_normal() {
+        let triangle = triangle();
+        let hit = triangle
+            .intersect(&from_back(0.25, 0.25), 0.0, f64::INFINITY)
+            .unwrap();
+        assert!((hit.t - 5.0).abs() < EPS);
+        assert_vec_eq(hit.normal, Vec3::new(0.0, 0.0, -1.0));
+        assert!(!hit.front_face);
+    }
+
+    #[test]
+    fn triangle_misses_parallel_rays_and_degenerate_triangles() {
+        let triangle = triangle();
+        let parallel = Ray::new(Vec3::new(-1.0, 0.2, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(triangle.intersect(&parallel, 0.0, f64::INFINITY).is_none());
+        assert!(triangle
+            .intersect(&from_front(0.2, 0.2), 0.0, 4.0)
+            .is_none());
+        assert!(triangle
+            .intersect(&from_front(0.2, 0.2), 6.0, 10.0)
+            .is_none());
+
+        let p = Vec3::new(1.0, 1.0, 0.0);
+        let line = Triangle::new(Vec3::ZERO, p, p * 2.0, Rgb::from_ints(0, 0, 0));
+        assert!(line
+            .intersect(&from_front(1.0, 1.0), 0.0, f64::INFINITY)
+            .is_none());
+    }
+
+    #[test]
+    fn vertex_normals_are_interpolated_and_face_the_ray() {
+        let n = Vec3::new(1.0, 0.0, 1.0).normalize();
+        let up = Vec3::new(0.0, 0.0, 1.0);
+        let smooth = triangle().with_normals(n, up, up);
+
+        let hit = smooth
+            .intersect(&from_front(0.0, 0.0), 0.0, f64::INFINITY)
+            .unwrap();
+        assert_vec_eq(hit.normal, n);
+        let hit = smooth
+            .intersect(&from_front(0.5, 0.5), 0.0, f64::INFINITY)
+            .unwrap();
+        assert_vec_eq(hit.normal, up);
+        let hit = smooth
+            .intersect(&from_back(0.0, 0.0), 0.0, f64::INFINITY)
+            .unwrap();
+        assert_vec_eq(hit.normal, -n);
+    }
+
+    /// A unit square in the z = 0 plane made of two triangles.
+    fn square() -> Mesh {
+        Mesh::new(
+            vec![
+                Vec3::ZERO,
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ],
+            vec![[0, 1, 2], [0, 2, 3]],
+            Rgb::from_ints(128, 128, 128),
+        )
+    }
+
+    #[test]
+    fn mesh_is_hit_on_every_triangle_and_shared_edge() {
+        for &acceleration in &[Acceleration::Bvh, Acceleration::BruteForce] {
+            let mesh = square().with_acceleration(acceleration);
+            for &(x, y) in &[(0.8, 0.2), (0.2, 0.8), (0.5, 0.5)] {
+                let hit = mesh
+                    .intersect(&from_front(x, y), 0.0, f64::INFINITY)
+                    .unwrap();
+                assert!((hit.t - 5.0).abs() < EPS);
+                assert!(mesh.occludes(&from_front(x, y), 0.0, f64::INFINITY));
+            }
+            assert!(mesh
+                .intersect(&from_front(1.2, 0.5), 0.0, f64::INFINITY)
+                .is_none());
+            assert!(!mesh.occludes(&from_front(0.5, 0.5), 0.0, 4.0));
+        }
+    }
+
+    #[test]
+    fn smooth_mesh_normals_average_the_adjacent_faces() {
+        // Two faces folded along the y axis
+        let mesh = Mesh::new(
+            vec![
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+                Vec3::new(1.0, 0.0, 1.0),
+                Vec3::new(-1.0, 0.0, 1.0),
+            ],
+            vec![[0, 2, 1], [0, 1, 3]],
+            Rgb::from_ints(128, 128, 128),
+        )
+        .with_smooth_normals();
+        assert_vec_eq(mesh.normals()[0], Vec3::new(0.0, 0.0, 1.0));
+        assert_vec_eq(mesh.normals()[2], Vec3::new(-1.0, 0.0, 1.0).normalize());
+    }
+
+    #[test]
+    fn acceleration_reaches_meshes_inside_other_objects() {
+        let mut moving = Moving::new(square(), Vec3::new(1.0, 0.0, 0.0));
+        moving.set_acceleration(Acceleration::BruteForce);
+        assert_eq!(moving.object.acceleration, Acceleration::BruteForce);
+    }
+}
@@ -0,0 +1,619 @@
+//! Wavefront OBJ and MTL import.
+//!
+//! Polygons are triangulated as fans, and every material used by a file
+//! becomes its own [`Mesh`]. MTL values map onto [`Material`] as follows:
+//!
+//! * `Kd` is the color, scaled from `0..=1` to `0..=255`
+//! * `Ns` is the specular exponent, matte if it is zero or `Ks` is black
+//! * `Pm` (metallic) is the reflectiveness, or the average of `Ks` for
+//!   `illum 3` materials without `Pm`
+//! * `d` (dissolve) is the opacity, `Tr` its inverse, the transparency
+//! * `Ni` is the index of refraction
+
+use crate::canvas::Rgb;
+use crate::math::Vec3;
+use crate::mesh::Mesh;
+use crate::object::{Material, WithMaterial};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        /// Unknown when parsing from a string.
+        path: Option<PathBuf>,
+        line: usize,
+        message: String,
+    },
+}
+
+impl ObjError {
+    fn parse(line: usize, message: impl Into<String>) -> ObjError {
+        ObjError::Parse {
+            path: None,
+            line,
+            message: message.into(),
+        }
+    }
+
+    fn in_file(self, file: &Path) -> ObjError {
+        match self {
+            ObjError::Parse {
+                path: None,
+                line,
+                message,
+            } => ObjError::Parse {
+                path: Some(file.to_path_buf()),
+                line,
+                message,
+            },
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => {
+                if let Some(path) = path {
+                    write!(f, "{}:", path.display())?;
+                }
+                write!(f, "{}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ObjError::Io { error, .. } => Some(error),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|error| ObjError::Io {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+/// Loads an OBJ file together with the MTL libraries it references, which are
+/// looked up relative to the OBJ file.
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<Mesh>, ObjError> {
+    let path = path.as_ref();
+    let source = read(path)?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut materials = HashMap::new();
+    for (i, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut words = line.split_whitespace();
+        if words.next() != Some("mtllib") {
+            continue;
+        }
+        let names: Vec<&str> = words.collect();
+        if names.is_empty() {
+            return Err(ObjError::parse(i + 1, "mtllib needs a file name").in_file(path));
+        }
+        for name in names {
+            let library = directory.join(name);
+            let parsed = parse_mtl(&read(&library)?).map_err(|e| e.in_file(&library))?;
+            materials.extend(parsed);
+        }
+    }
+
+    parse(&source, &materials).map_err(|e| e.in_file(path))
+}
+
+pub fn load_mtl(path: impl AsRef<Path>) -> Result<HashMap<String, Material>, ObjError> {
+    let path = path.as_ref();
+    parse_mtl(&read(path)?).map_err(|e| e.in_file(path))
+}
+
+fn numbers<const N: usize>(
+    line: usize,
+    keyword: &str,
+    words: &[&str],
+    required: usize,
+) -> Result<[f64; N], ObjError> {
+    if words.len() < required || words.len() > N {
+        let expected = if required == N {
+            format!("{}", N)
+        } else {
+            format!("{} to {}", required, N)
+        };
+        return Err(ObjError::parse(
+            line,
+            format!(
+                "{} expects {} numbers, found {}",
+                keyword,
+                expected,
+                words.len()
+            ),
+        ));
+    }
+
+    let mut values = [0.0; N];
+    for (value, word) in values.iter_mut().zip(words) {
+        *value = word.parse().map_err(|_| {
+            ObjError::parse(line, format!("{}: '{}' is not a number", keyword, word))
+        })?;
+    }
+    Ok(values)
+}
+
+/// Parses MTL source into materials by name.
+pub fn parse_mtl(source: &str) -> Result<HashMap<String, Material>, ObjError> {
+    struct Entry {
+        name: String,
+        line: usize,
+        diffuse: [f64; 3],
+        specular: [f64; 3],
+        exponent: f64,
+        metallic: Option<f64>,
+        illum: i32,
+        dissolve: f64,
+        refractive_index: f64,
+    }
+
+    impl Entry {
+        fn into_material(self) -> (String, Material) {
+            let to_channel = |v: f64| v.clamp(0.0, 1.0) * 255.0;
+            let color = Rgb {
+                red: to_channel(self.diffuse[0]),
+                green: to_channel(self.diffuse[1]),
+                blue: to_channel(self.diffuse[2]),
+            };
+
+            let shiny = self.specular.iter().any(|&k| k > 0.0) && self.exponent > 0.0;
+            let specular = if shiny {
+                self.exponent.round() as i32
+            } else {
+                -1
+            };
+
+            let reflective = match self.metallic {
+                Some(metallic) => metallic,
+                None if self.illum == 3 => self.specular.iter().sum::<f64>() / 3.0,
+                None => 0.0,
+            };
+
+            let material = Material::new(color)
+                .with_specular(specular)
+                .with_reflective(reflective.clamp(0.0, 1.0))
+                .with_transparency((1.0 - self.dissolve).clamp(0.0, 1.0))
+                .with_refractive_index(self.refractive_index);
+            (self.name, material)
+        }
+    }
+
+    fn finish(entry: Entry, materials: &mut HashMap<String, Material>) -> Result<(), ObjError> {
+        if materials.contains_key(&entry.name) {
+            return Err(ObjError::parse(
+                entry.line,
+                format!("material '{}' is defined twice", entry.name),
+            ));
+        }
+        let (name, material) = entry.into_material();
+        materials.insert(name, material);
+        Ok(())
+    }
+
+    let mut materials = HashMap::new();
+    let mut current: Option<Entry> = None;
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.split('#').next().unwrap_or("");
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (keyword, args) = match words.split_first() {
+            Some((keyword, args)) => (*keyword, args),
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            if args.is_empty() {
+                return Err(ObjError::parse(line_number, "newmtl needs a name"));
+            }
+            if let Some(entry) = current.take() {
+                finish(entry, &mut materials)?;
+            }
+            current = Some(Entry {
+                name: args.join(" "),
+                line: line_number,
+                diffuse: [0.8, 0.8, 0.8],
+                specular: [0.0, 0.0, 0.0],
+                exponent: 0.0,
+                metallic: None,
+                illum: 2,
+                dissolve: 1.0,
+                refractive_index: 1.0,
+            });
+            continue;
+        }
+
+        let entry = match current.as_mut() {
+            Some(entry) => entry,
+            None => {
+                return Err(ObjError::parse(
+                    line_number,
+                    format!("{} before the first newmtl", keyword),
+                ))
+            }
+        };
+        match keyword {
+            "Kd" => entry.diffuse = numbers(line_number, keyword, args, 3)?,
+            "Ks" => entry.specular = numbers(line_number, keyword, args, 3)?,
+            "Ns" => entry.exponent = numbers::<1>(line_number, keyword, args, 1)?[0],
+            "Pm" => entry.metallic = Some(numbers::<1>(line_number, keyword, args, 1)?[0]),
+            "d" => entry.dissolve = numbers::<1>(line_number, keyword, args, 1)?[0],
+            "Tr" => entry.dissolve = 1.0 - numbers::<1>(line_number, keyword, args, 1)?[0],
+            "Ni" => entry.refractive_index = numbers::<1>(line_number, keyword, args, 1)?[0],
+            "illum" => {
+                entry.illum = numbers::<1>(line_number, keyword, args, 1)?[0] as i32;
+            }
+            // Everything else (ambient, textures, PBR extensions, ...) has no
+            // counterpart in our materials
+            _ => {}
+        }
+    }
+
+    if let Some(entry) = current.take() {
+        finish(entry, &mut materials)?;
+    }
+    Ok(materials)
+}
+
+/// Resolves a 1-based, possibly negative (counting from the end) OBJ index.
+fn resolve_index(line: usize, word: &str, kind: &str, count: usize) -> Result<usize, ObjError> {
+    let index: i64 = word
+        .parse()
+        .map_err(|_| ObjError::parse(line, format!("'{}' is not a {} index", word, kind)))?;
+
+    let resolved = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(ObjError::parse(
+            line,
+            format!(
+                "{} index {} is out of range, {} defined so far",
+                kind, index, count
+            ),
+        ));
+    }
+    Ok(resolved as usize)
+}
+
+/// One mesh in the making, collecting the faces that use one material.
+#[derive(Default)]
+struct Builder {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<[f64; 2]>,
+    triangles: Vec<[usize; 3]>,
+    /// Mesh vertex for every distinct position/uv/normal triple
+    vertices: HashMap<(usize, Option<usize>, Option<usize>), usize>,
+    missing_normals: bool,
+    missing_uvs: bool,
+}
+
+impl Builder {
+    fn into_mesh(self, material: Material) -> Mesh {
+        let mut mesh =
+            Mesh::new(self.positions, self.triangles, material.color).with_material(material);
+        if !self.missing_normals {
+            mesh = mesh.with_normals(self.normals);
+        }
+        if !self.missing_uvs {
+            mesh = mesh.with_uvs(self.uvs);
+        }
+        mesh
+    }
+}
+
+/// Parses OBJ source. `mtllib` statements are ignored, `usemtl` looks the
+/// material up in `materials`.
+pub fn parse(source: &str, materials: &HashMap<String, Material>) -> Result<Vec<Mesh>, ObjError> {
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<[f64; 2]> = Vec::new();
+
+    // Meshes in order of first use, `None` stands for the default material
+    let mut builders: Vec<(Option<&str>, Builder)> = Vec::new();
+    let mut current = 0;
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.split('#').next().unwrap_or("");
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (keyword, args) = match words.split_first() {
+            Some((keyword, args)) => (*keyword, args),
+            None => continue,
+        };
+
+        match keyword {
+            "v" => {
+                // The optional weight only matters for rational curves
+                let [x, y, z, _] = numbers(line_number, keyword, args, 3)?;
+                positions.push(Vec3::new(x, y, z));
+            }
+            "vn" => {
+                let [x, y, z] = numbers(line_number, keyword, args, 3)?;
+                normals.push(Vec3::new(x, y, z));
+            }
+            "vt" => {
+                let [u, v, _] = numbers(line_number, keyword, args, 1)?;
+                uvs.push([u, v]);
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+                let (key, _) = materials.get_key_value(&name).ok_or_else(|| {
+                    ObjError::parse(line_number, format!("unknown material '{}'", name))
+                })?;
+                current = match builders.iter().position(|(n, _)| *n == Some(key)) {
+                    Some(index) => index,
+                    None => {
+                        builders.push((Some(key), Builder::default()));
+                        builders.len() - 1
+                    }
+                };
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(ObjError::parse(
+                        line_number,
+                        format!("a face needs at least 3 vertices, found {}", args.len()),
+                    ));
+                }
+                if builders.is_empty() {
+                    builders.push((None, Builder::default()));
+                }
+                let builder = &mut builders[current].1;
+
+                let mut face = Vec::with_capacity(args.len());
+                for word in args {
+                    let mut parts = word.split('/');
+                    let position = match parts.next() {
+                        Some(p) => resolve_index(line_number, p, "vertex", positions.len())?,
+                        None => unreachable!("split always yields one part"),
+                    };
+                    let uv = match parts.next() {
+                        Some("") | None => None,
+                        Some(t) => Some(resolve_index(line_number, t, "texture", uvs.len())?),
+                    };
+                    let normal = match parts.next() {
+                        Some("") | None => None,
+                        Some(n) => Some(resolve_index(line_number, n, "normal", normals.len())?),
+                    };
+                    if parts.next().is_some() {
+                        return Err(ObjError::parse(
+                            line_number,
+                            format!("malformed face vertex '{}'", word),
+                        ));
+                    }
+
+                    let key = (position, uv, normal);
+                    let vertex = match builder.vertices.get(&key) {
+                        Some(&vertex) => vertex,
+                        None => {
+                            builder.positions.push(positions[position]);
+                            builder.uvs.push(uv.map_or([0.0, 0.0], |t| uvs[t]));
+                            builder
+                                .normals
+                                .push(normal.map_or(Vec3::ZERO, |n| normals[n]));
+                            builder.missing_uvs |= uv.is_none();
+                            builder.missing_normals |= normal.is_none();
+                            builder.vertices.insert(key, builder.positions.len() - 1);
+                            builder.positions.len() - 1
+                        }
+                    };
+                    face.push(vertex);
+                }
+
+                // Fan triangulation, fine for the convex polygons exporters write
+                for j in 1..face.len() - 1 {
+                    builder.triangles.push([face[0], face[j], face[j + 1]]);
+                }
+            }
+            // Groups, smoothing groups, lines, free-form geometry, ...
+            _ => {}
+        }
+    }
+
+    Ok(builders
+        .into_iter()
+        .filter(|(_, builder)| !builder.triangles.is_empty())
+        .map(|(name, builder)| {
+            let material = name.map_or_else(Material::default, |name| materials[name].clone());
+            builder.into_mesh(material)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE_VERTICES: &str = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+";
+
+    fn parse_one(faces: &str) -> Mesh {
+        let source = format!("{}{}", SQUARE_VERTICES, faces);
+        let mut meshes = parse(&source, &HashMap::new()).unwrap();
+        assert_eq!(meshes.len(), 1);
+        meshes.pop().unwrap()
+    }
+
+    fn parse_error(source: &str) -> String {
+        parse(source, &HashMap::new()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn faces_with_positions_uvs_and_normals() {
+        let mesh = parse_one("f 1/1/1 2/2/1 3/3/1\n");
+        assert_eq!(mesh.triangles(), &[[0, 1, 2]]);
+        assert_eq!(mesh.positions()[2], Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(mesh.uvs(), &[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0]]);
+        assert_eq!(mesh.normals(), &[Vec3::new(0.0, 0.0, 1.0); 3]);
+    }
+
+    #[test]
+    fn faces_with_positions_and_normals() {
+        let mesh = parse_one("f 1//1 2//1 3//1\n");
+        assert_eq!(mesh.normals().len(), 3);
+        assert!(mesh.uvs().is_empty());
+    }
+
+    #[test]
+    fn faces_with_positions_and_uvs() {
+        let mesh = parse_one("f 1/1 2/2 3/3\n");
+        assert_eq!(mesh.uvs().len(), 3);
+        assert!(mesh.normals().is_empty());
+    }
+
+    #[test]
+    fn vertices_missing_a_normal_make_the_mesh_flat() {
+        let mesh = parse_one("f 1//1 2//1 3\n");
+        assert!(mesh.normals().is_empty());
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_vertex() {
+        let mesh = parse_one("f -4/-4/-1 -3/-3/-1 -2/-2/-1\n");
+        assert_eq!(
+            mesh.positions(),
+            &[
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(1.0, 1.0, 0.0),
+            ]
+        );
+        assert_eq!(mesh.uvs()[2], [1.0, 1.0]);
+
+        // Relative to what is defined when the face is read
+        let mesh = parse_one("f 1 2 -1\nv 5 5 5\nf 1 2 -1\n");
+        assert_eq!(mesh.positions()[2], Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(mesh.positions()[3], Vec3::new(5.0, 5.0, 5.0));
+    }
+
+    #[test]
+    fn polygons_are_triangulated_as_fans() {
+        let quad = parse_one("f 1 2 3 4\n");
+        assert_eq!(quad.triangles(), &[[0, 1, 2], [0, 2, 3]]);
+
+        let source = "v 0 0 0\nv 1 0 0\nv 2 1 0\nv 1 2 0\nv 0 1 0\nf 1 2 3 4 5\n";
+        let pentagon = parse(source, &HashMap::new()).unwrap().pop().unwrap();
+        assert_eq!(pentagon.triangles(), &[[0, 1, 2], [0, 2, 3], [0, 3, 4]]);
+    }
+
+    #[test]
+    fn shared_vertices_are_reused() {
+        let mesh = parse_one("f 1 2 3\nf 1 3 4\n");
+        assert_eq!(mesh.positions().len(), 4);
+        assert_eq!(mesh.triangles(), &[[0, 1, 2], [0, 2, 3]]);
+    }
+
+    #[test]
+    fn usemtl_splits_faces_into_one_mesh_per_material() {
+        let materials = parse_mtl(
+            "\
+newmtl red
+Kd 1 0 0
+Ks 0.5 0.5 0.5
+Ns 100
+newmtl mirror
+Kd 0 0 0
+Pm 0.8
+",
+        )
+        .unwrap();
+        let source = format!(
+            "{}f 1 2 3\nusemtl red\nf 1 3 4\nusemtl mirror\nf 2 3 4\nusemtl red\nf 1 2 4\n",
+            SQUARE_VERTICES
+        );
+        let meshes = parse(&source, &materials).unwrap();
+        assert_eq!(meshes.len(), 3);
+
+        assert_eq!(meshes[0].material, Material::default());
+        assert_eq!(meshes[1].material, materials["red"]);
+        assert_eq!(meshes[1].material.color, Rgb::from_ints(255, 0, 0));
+        assert_eq!(meshes[1].material.specular, 100);
+        assert_eq!(meshes[1].triangles().len(), 2);
+        assert_eq!(meshes[2].material.reflective, 0.8);
+        assert_eq!(meshes[2].material.specular, -1);
+    }
+
+    #[test]
+    fn unknown_materials_are_reported_with_their_line() {
+        let source = format!("{}usemtl gold\n", SQUARE_VERTICES);
+        assert_eq!(parse_error(&source), "10: unknown material 'gold'");
+    }
+
+    #[test]
+    fn bad_indices_are_reported_with_their_line() {
+        let face = |face: &str| parse_error(&format!("{}{}\n", SQUARE_VERTICES, face));
+        assert_eq!(
+            face("f 1 2 5"),
+            "10: vertex index 5 is out of range, 4 defined so far"
+        );
+        assert_eq!(
+            face("f 1 2 -5"),
+            "10: vertex index -5 is out of range, 4 defined so far"
+        );
+        assert_eq!(
+            face("f 0 1 2"),
+            "10: vertex index 0 is out of range, 4 defined so far"
+        );
+        assert_eq!(
+            face("f 1/9 2/1 3/1"),
+            "10: texture index 9 is out of range, 4 defined so far"
+        );
+        assert_eq!(
+            face("f 1//2 2//1 3//1"),
+            "10: normal index 2 is out of range, 1 defined so far"
+        );
+        assert_eq!(face("f 1 2 x"), "10: 'x' is not a vertex index");
+        assert_eq!(face("f 1/1/1/1 2 3"), "10: malformed face vertex '1/1/1/1'");
+        assert_eq!(
+            face("f 1 2"),
+            "10: a face needs at least 3 vertices, found 2"
+        );
+    }
+
+    #[test]
+    fn mtl_errors_are_reported_with_their_line() {
+        let error = parse_mtl("newmtl a\nKd 1 0\n").unwrap_err().to_string();
+        assert!(error.starts_with("2: "), "{}", error);
+        let error = parse_mtl("newmtl a\n\nnewmtl a\n").unwrap_err().to_string();
+        assert_eq!(error, "3: material 'a' is defined twice");
+    }
+}
@@ -0,0 +1,373 @@
+use crate::bvh::{Aabb, Acceleration};
+use crate::canvas::Rgb;
+use crate::math::Vec3;
+use std::fmt;
+
+/// The half-line `origin + t * direction`. `direction` is not normalized.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+    /// When the ray was sent, as a fraction of the frame from `0.0` to `1.0`.
+    /// [`Moving`] objects are hit where they are at that time.
+    pub time: f64,
+}
+
+impl Ray {
+    /// A ray at time `0.0`.
+    pub fn new(origin: Vec3, direction: Vec3) -> Ray {
+        Ray {
+            origin,
+            direction,
+            time: 0.0,
+        }
+    }
+
+    pub fn with_time(mut self, time: f64) -> Ray {
+        self.time = time;
+        self
+    }
+
+    pub fn at(&self, t: f64) -> Vec3 {
+        self.origin + self.direction * t
+    }
+}
+
+/// How a surface reacts to light.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub color: Rgb,
+    /// Specular exponent, `-1` for matte surfaces.
+    pub specular: i32,
+    /// How much of the reflected ray's color is mixed in, from `0.0` to `1.0`.
+    pub reflective: f64,
+    /// How much light passes through the surface, from `0.0` (opaque) to
+    /// `1.0` (clear glass).
+    pub transparency: f64,
+    /// Index of refraction of the inside, the outside is assumed to be air.
+    pub refractive_index: f64,
+}
+
+impl Material {
+    /// A matte, non reflective material.
+    pub fn new(color: Rgb) -> Material {
+        Material {
+            color,
+            specular: -1,
+            reflective: 0.0,
+            transparency: 0.0,
+            refractive_index: 1.0,
+        }
+    }
+
+    pub fn with_specular(mut self, specular: i32) -> Material {
+        self.specular = specular;
+        self
+    }
+
+    pub fn with_reflective(mut self, reflective: f64) -> Material {
+        self.reflective = reflective;
+        self
+    }
+
+    pub fn with_transparency(mut self, transparency: f64) -> Material {
+        self.transparency = transparency;
+        self
+    }
+
+    pub fn with_refractive_index(mut self, refractive_index: f64) -> Material {
+        self.refractive_index = refractive_index;
+        self
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material::new(Rgb::from_ints(255, 255, 255))
+    }
+}
+
+/// Builder methods shared by every object made of a single [`Material`].
+pub trait WithMaterial: Sized {
+    fn material_mut(&mut self) -> &mut Material;
+
+    fn with_material(mut self, material: Material) -> Self {
+        *self.material_mut() = material;
+        self
+    }
+
+    fn with_specular(mut self, specular: i32) -> Self {
+        self.material_mut().specular = specular;
+        self
+    }
+
+    fn with_reflective(mut self, reflective: f64) -> Self {
+        self.material_mut().reflective = reflective;
+        self
+    }
+
+    fn with_transparency(mut self, transparency: f64) -> Self {
+        self.material_mut().transparency = transparency;
+        self
+    }
+
+    fn with_refractive_index(mut self, refractive_index: f64) -> Self {
+        self.material_mut().refractive_index = refractive_index;
+        self
+    }
+}
+
+/// Where a ray meets an object.
+#[derive(Clone, Copy, Debug)]
+pub struct Hit<'a> {
+    /// Ray parameter of the hit, the point is `ray.at(t)`.
+    pub t: f64,
+    pub point: Vec3,
+    /// Unit surface normal at `point`, facing the ray.
+    pub normal: Vec3,
+    /// Whether the ray hit the front (outer) face, rather than coming from
+    /// inside the object or behind the surface.
+    pub front_face: bool,
+    pub material: &'a Material,
+}
+
+/// Anything a ray can hit.
+///
+/// Implementors only need to know their own geometry, shading, shadows and
+/// reflections are handled by [`World`](crate::raytracer::World) for all of them.
+pub trait Object: Send + Sync + fmt::Debug {
+    /// The closest hit with `min_t <= t <= max_t`, if any.
+    fn intersect(&self, ray: &Ray, min_t: f64, max_t: f64) -> Option<Hit<'_>>;
+
+    /// A box containing the whole object, `None` if it is unbounded.
+    ///
+    /// Unbounded objects are left out of the BVH and tested against every ray.
+    fn bounds(&self) -> Option<Aabb>;
+
+    /// Whether anything is hit in `min_t..=max_t`, used for shadow rays.
+    ///
+    /// Only needs overriding when it can be answered cheaper than `intersect`.
+    fn occludes(&self, ray: &Ray, min_t: f64, max_t: f64) -> bool {
+        self.intersect(ray, min_t, max_t).is_some()
+    }
+
+    /// Switches the acceleration structure inside the object, if it has one.
+    ///
+    /// Only needs overriding by objects made of many primitives, like
+    /// [`Mesh`](crate::mesh::Mesh), and by wrappers passing it on.
+    fn set_acceleration(&mut self, _acceleration: Acceleration) {}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sphere {
+    pub center: Vec3,
+    pub radius: f64,
+    pub material: Material,
+}
+
+impl Sphere {
+    /// A matte, non reflective sphere.
+    pub fn new(center: Vec3, radius: f64, color: Rgb) -> Sphere {
+        Sphere {
+            center,
+            radius,
+            material: Material::new(color),
+        }
+    }
+}
+
+impl WithMaterial for Sphere {
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+}
+
+impl Object for Sphere {
+    fn intersect(&self, ray: &Ray, min_t: f64, max_t: f64) -> Option<Hit<'_>> {
+        let (t1, t2) = intersect_ray_sphere(&ray.origin, &ray.direction, self);
+        if t1 == f64::INFINITY {
+            return None;
+        }
+        let t = [t1.min(t2), t1.max(t2)]
+            .iter()
+            .copied()
+            .find(|&t| (min_t <= t) && (t <= max_t))?;
+
+        let point = ray.at(t);
+        let outward = (point - self.center) / self.radius;
+        let front_face = outward.dot(&ray.direction) <= 0.0;
+        Some(Hit {
+            t,
+            point,
+            normal: if front_face { outward } else { -outward },
+            front_face,
+            material: &self.material,
+        })
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.center - r, self.center + r))
+    }
+}
+
+/// Both solutions of `|o + t * d - center| = radius`, infinite on a miss.
+pub fn intersect_ray_sphere(o: &Vec3, d: &Vec3, s: &Sphere) -> (f64, f64) {
+    let r = s.radius;
+    let co = *o - s.center;
+
+    let a = d.dot(d);
+    let b = 2.0 * co.dot(d);
+    let c = co.dot(&co) - r * r;
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return (f64::INFINITY, f64::INFINITY);
+    }
+    let t1 = (-b + discriminant.sqrt()) / (2.0 * a);
+    let t2 = (-b - discriminant.sqrt()) / (2.0 * a);
+    (t1, t2)
+}
+
+/// An object moving in a straight line during the frame, for motion blur.
+///
+/// At time `0.0` it is where `object` is, by time `1.0` it has moved by
+/// `translation`. Only rays sent at different times, see
+/// [`Camera::with_shutter`](crate::scene::Camera::with_shutter), blur it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Moving<O> {
+    pub object: O,
+    pub translation: Vec3,
+}
+
+impl<O: Object> Moving<O> {
+    pub fn new(object: O, translation: Vec3) -> Moving<O> {
+        Moving {
+            object,
+            translation,
+        }
+    }
+
+    /// `ray` moved along with the object, so the object can stay put.
+    fn relative_ray(&self, ray: &Ray) -> (Ray, Vec3) {
+        let offset = self.translation * ray.time;
+        let relative = Ray::new(ray.origin - offset, ray.direction).with_time(ray.time);
+        (relative, offset)
+    }
+}
+
+impl<O: Object> Object for Moving<O> {
+    fn intersect(&self, ray: &Ray, min_t: f64, max_t: f64) -> Option<Hit<'_>> {
+        let (relative, offset) = self.relative_ray(ray);
+        let hit = self.object.intersect(&relative, min_t, max_t)?;
+        Some(Hit {
+            point: hit.point + offset,
+            ..hit
+        })
+    }
+
+    /// Everywhere the object goes from time `0.0` to `1.0`.
+    fn bounds(&self) -> Option<Aabb> {
+        let start = self.object.bounds()?;
+        let end = Aabb::new(start.min + self.translation, start.max + self.translation);
+        Some(start.union(&end))
+    }
+
+    fn occludes(&self, ray: &Ray, min_t: f64, max_t: f64) -> bool {
+        let (relative, _) = self.relative_ray(ray);
+        self.object.occludes(&relative, min_t, max_t)
+    }
+
+    fn set_acceleration(&mut self, acceleration: Acceleration) {
+        self.object.set_acceleration(acceleration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f64 = 1e-9;
+
+    /// Radius 1 around `(0, 0, 5)`.
+    fn sphere() -> Sphere {
+        Sphere::new(Vec3::new(0.0, 0.0, 5.0), 1.0, Rgb::from_ints(128, 128, 128))
+    }
+
+    /// Along z from the origin, entering the sphere at 4 and leaving at 6.
+    fn forward() -> Ray {
+        Ray::new(Vec3::ZERO, Vec3::new(0.0, 0.0, 1.0))
+    }
+
+    fn assert_vec_eq(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < EPS, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn sphere_hits_the_nearest_root() {
+        let sphere = sphere();
+        let hit = sphere.intersect(&forward(), 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t - 4.0).abs() < EPS);
+        assert_vec_eq(hit.point, Vec3::new(0.0, 0.0, 4.0));
+        assert_vec_eq(hit.normal, Vec3::new(0.0, 0.0, -1.0));
+        assert!(hit.front_face);
+        assert_eq!(hit.material, &sphere.material);
+
+        // The direction's length only scales t
+        let long = Ray::new(Vec3::ZERO, Vec3::new(0.0, 0.0, 2.0));
+        let hit = sphere.intersect(&long, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t - 2.0).abs() < EPS);
+    }
+
+    #[test]
+    fn sphere_hits_stay_inside_the_range() {
+        let sphere = sphere();
+        let far = |min_t, max_t| sphere.intersect(&forward(), min_t, max_t).map(|hit| hit.t);
+        assert_eq!(far(4.5, f64::INFINITY), Some(6.0));
+        assert_eq!(far(4.0, 4.0), Some(4.0));
+        assert_eq!(far(0.0, 6.0), Some(4.0));
+        assert_eq!(far(0.0, 3.9), None);
+        assert_eq!(far(6.1, f64::INFINITY), None);
+        assert_eq!(far(4.1, 5.9), None);
+    }
+
+    #[test]
+    fn sphere_normals_face_rays_from_inside() {
+        let inside = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(1.0, 0.0, 0.0));
+        let sphere = sphere();
+        let hit = sphere.intersect(&inside, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t - 1.0).abs() < EPS);
+        assert_vec_eq(hit.point, Vec3::new(1.0, 0.0, 5.0));
+        assert_vec_eq(hit.normal, Vec3::new(-1.0, 0.0, 0.0));
+        assert!(!hit.front_face);
+    }
+
+    #[test]
+    fn sphere_misses_rays_passing_by_or_pointing_away() {
+        let sphere = sphere();
+        let by = Ray::new(Vec3::new(1.01, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(sphere.intersect(&by, 0.0, f64::INFINITY).is_none());
+        let away = Ray::new(Vec3::ZERO, Vec3::new(0.0, 0.0, -1.0));
+        assert!(sphere.intersect(&away, 0.0, f64::INFINITY).is_none());
+
+        let grazing = Ray::new(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let hit = sphere.intersect(&grazing, 0.0, f64::INFINITY).unwrap();
+        assert!((hit.t - 5.0).abs() < EPS);
+    }
+
+    #[test]
+    fn occludes_defaults_to_intersect() {
+        let sphere = sphere();
+        assert!(sphere.occludes(&forward(), 0.0, f64::INFINITY));
+        assert!(sphere.occludes(&forward(), 0.0, 4.5));
+        assert!(!sphere.occludes(&forward(), 0.0, 3.5));
+        assert!(!sphere.occludes(&forward(), 6.5, f64::INFINITY));
+    }
+
+    #[test]
+    fn sphere_bounds_touch_the_surface() {
+        let bounds = sphere().bounds().unwrap();
+        assert_vec_eq(bounds.min, Vec3::new(-1.0, -1.0, 4.0));
+        assert_vec_eq(bounds.max, Vec3::new(1.0, 1.0, 6.0));
+    }
+}
@@ -0,0 +1,391 @@
+use crate::bvh::Aabb;
+use crate::canvas::Rgb;
+use crate::math::Vec3;
+use crate::object::{Hit, Material, Object, Ray, WithMaterial};
+
+/// Where `ray` crosses the plane through `point` with normal `normal`.
+///
+/// Rays parallel to the plane never cross it. With `two_sided` unset, rays
+/// coming from behind the normal pass straight through.
+fn intersect_plane(
+    ray: &Ray,
+    point: &Vec3,
+    normal: &Vec3,
+    two_sided: bool,
+    min_t: f64,
+    max_t: f64,
+) -> Option<(f64, Vec3)> {
+    let denominator = normal.dot(&ray.direction);
+    if denominator.abs() < 1e-12 || (!two_sided && denominator > 0.0) {
+        return None;
+    }
+
+    let t = normal.dot(&(*point - ray.origin)) / denominator;
+    if t < min_t || t > max_t {
+        return None;
+    }
+    Some((t, ray.at(t)))
+}
+
+/// Added around the bounds of flat shapes, whose boxes are otherwise flat too.
+const FLAT_MARGIN: f64 = 1e-6;
+
+/// The normal of a flat surface, turned to face whoever is looking at it.
+fn facing(normal: &Vec3, ray: &Ray) -> Vec3 {
+    if normal.dot(&ray.direction) > 0.0 {
+        -*normal
+    } else {
+        *normal
+    }
+}
+
+/// An infinite plane, visible from both sides.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plane {
+    pub point: Vec3,
+    /// Unit normal.
+    pub normal: Vec3,
+    pub material: Material,
+}
+
+impl Plane {
+    /// The plane through `point` perpendicular to `normal`.
+    pub fn new(point: Vec3, normal: Vec3, color: Rgb) -> Plane {
+        Plane {
+            point,
+            normal: normal.normalize(),
+            material: Material::new(color),
+        }
+    }
+}
+
+impl WithMaterial for Plane {
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+}
+
+impl Object for Plane {
+    fn intersect(&self, ray: &Ray, min_t: f64, max_t: f64) -> Option<Hit<'_>> {
+        let (t, point) = intersect_plane(ray, &self.point, &self.normal, true, min_t, max_t)?;
+        Some(Hit {
+            t,
+            point,
+            normal: facing(&self.normal, ray),
+            front_face: self.normal.dot(&ray.direction) <= 0.0,
+            material: &self.material,
+        })
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+/// The parallelogram `corner + a * u + b * v` for `a` and `b` in `0..=1`.
+///
+/// Its front face is the side `u.cross(&v)` points to. One-sided quads are
+/// invisible, and cast no shadows, when seen from the back.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quad {
+    pub corner: Vec3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub material: Material,
+    pub two_sided: bool,
+}
+
+impl Quad {
+    /// A two-sided quad.
+    pub fn new(corner: Vec3, u: Vec3, v: Vec3, color: Rgb) -> Quad {
+        Quad {
+            corner,
+            u,
+            v,
+            material: Material::new(color),
+            two_sided: true,
+        }
+    }
+
+    pub fn one_sided(mut self) -> Quad {
+        self.two_sided = false;
+        self
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        self.u.cross(&self.v).normalize()
+    }
+}
+
+impl WithMaterial for Quad {
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+}
+
+impl Object for Quad {
+    fn intersect(&self, ray: &Ray, min_t: f64, max_t: f64) -> Option<Hit<'_>> {
+        let n = self.u.cross(&self.v);
+        let normal = n.normalize();
+        let (t, point) = intersect_plane(ray, &self.corner, &normal, self.two_sided, min_t, max_t)?;
+
+        // Coordinates of the hit along u and v
+        let w = n / n.dot(&n);
+        let p = point - self.corner;
+        let a = w.dot(&p.cross(&self.v));
+        let b = w.dot(&self.u.cross(&p));
+        if !(0.0..=1.0).contains(&a) || !(0.0..=1.0).contains(&b) {
+            return None;
+        }
+
+        Some(Hit {
+            t,
+            point,
+            normal: facing(&normal, ray),
+            front_face: normal.dot(&ray.direction) <= 0.0,
+            material: &self.material,
+        })
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let (c, u, v) = (self.corner, self.u, self.v);
+        Some(Aabb::from_points(&[c, c + u, c + v, c + u + v]).padded(FLAT_MARGIN))
+    }
+}
+
+/// A flat, round disk. Sidedness works like for [`Quad`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Disk {
+    pub center: Vec3,
+    /// Unit normal, pointing out of the front face.
+    pub normal: Vec3,
+    pub radius: f64,
+    pub material: Material,
+    pub two_sided: bool,
+}
+
+impl Disk {
+    /// A two-sided disk.
+    pub fn new(center: Vec3, normal: Vec3, radius: f64, color: Rgb) -> Disk {
+        Disk {
+            center,
+            normal: normal.normalize(),
+            radius,
+            material: Material::new(color),
+            two_sided: true,
+        }
+    }
+
+    pub fn one_sided(mut self) -> Disk {
+        self.two_sided = false;
+        self
+    }
+}
+
+impl WithMaterial for Disk {
+    fn material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+}
+
+impl Object for Disk {
+    fn intersect(&self, ray: &Ray, min_t: f64, max_t: f64) -> Option<Hit<'_>> {
+        let (t, point) = intersect_plane(
+            ray,
+            &self.center,
+            &self.normal,
+            self.two_sided,
+            min_t,
+            max_t,
+        )?;
+        if (point - self.center).length_squared() > self.radius * self.radius {
+            return None;
+        }
+
+        Some(Hit {
+            t,
+            point,
+            normal: facing(&self.normal, ray),
+            front_face: self.normal.dot(&ray.direction) <= 0.0,
+            material: &self.material,
+        })
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        // How far the rim reaches along each axis
+        let n = &self.normal;
+        let reach = |n: f64| self.radius * (1.0 - n * n).max(0.0).sqrt();
+        let extent = Vec3::new(reach(n.x), reach(n.y), reach(n.z));
+        Some(Aabb::new(self.center - extent, self.center + extent).padded(FLAT_MARGIN))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPS: f64 = 1e-9;
+
+    fn gray() -> Rgb {
+        Rgb::from_ints(128, 128, 128)
+    }
+
+    fn down_from(x: f64, z: f64) -> Ray {
+        Ray::new(Vec3::new(x, 5.0, z), Vec3::new(0.0, -1.0, 0.0))
+    }
+
+    fn up_from(x: f64, z: f64) -> Ray {
+        Ray::new(Vec3::new(x, -5.0, z), Vec3::new(0.0, 1.0, 0.0))
+    }
+
+    fn assert_vec_eq(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < EPS, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn plane_is_hit_from_both_sides() {
+        let plane = Plane::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 2.0, 0.0), gray());
+
+        let hit = plane
+            .intersect(&down_from(3.0, -7.0), 0.0, f64::INFINITY)
+            .unwrap();
+        assert!((hit.t - 4.0).abs() < EPS);
+        assert_vec_eq(hit.point, Vec3::new(3.0, 1.0, -7.0));
+        assert_vec_eq(hit.normal, Vec3::new(0.0, 1.0, 0.0));
+        assert!(hit.front_face);
+
+        let hit = plane
+            .intersect(&up_from(0.0, 0.0), 0.0, f64::INFINITY)
+            .unwrap();
+        assert!((hit.t - 6.0).abs() < EPS);
+        assert_vec_eq(hit.normal, Vec3::new(0.0, -1.0, 0.0));
+        assert!(!hit.front_face);
+    }
+
+    #[test]
+    fn plane_misses_parallel_rays_and_hits_outside_the_range() {
+        let plane = Plane::new(Vec3::ZERO, Vec3::new(0.0, 1.0, 0.0), gray());
+        let parallel = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(plane.intersect(&parallel, 0.0, f64::INFINITY).is_none());
+        assert!(plane.intersect(&down_from(0.0, 0.0), 0.0, 4.0).is_none());
+        assert!(plane.intersect(&down_from(0.0, 0.0), 6.0, 10.0).is_none());
+        assert!(plane.bounds().is_none());
+    }
+
+    fn unit_quad() -> Quad {
+        // In the y = 0 plane, facing up
+        Quad::new(
+            Vec3::ZERO,
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            gray(),
+        )
+    }
+
+    #[test]
+    fn quad_is_hit_inside_its_edges() {
+        let quad = unit_quad();
+        assert_vec_eq(quad.normal(), Vec3::new(0.0, 1.0, 0.0));
+        let hit = quad
+            .intersect(&down_from(0.25, 0.75), 0.0, f64::INFINITY)
+            .unwrap();
+        assert!((hit.t - 5.0).abs() < EPS);
+        assert!(hit.front_face);
+        assert!(quad
+            .intersect(&down_from(1.0, 1.0), 0.0, f64::INFINITY)
+            .is_some());
+
+        for &(x, z) in &[(-0.01, 0.5), (1.01, 0.5), (0.5, -0.01), (0.5, 1.01)] {
+            assert!(quad
+                .intersect(&down_from(x, z), 0.0, f64::INFINITY)
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn one_sided_quad_is_invisible_from_the_back() {
+        let two_sided = unit_quad();
+        let hit = two_sided
+            .intersect(&up_from(0.5, 0.5), 0.0, f64::INFINITY)
+            .unwrap();
+        assert!(!hit.front_face);
+        assert_vec_eq(hit.normal, Vec3::new(0.0, -1.0, 0.0));
+
+        let one_sided = unit_quad().one_sided();
+        assert!(one_sided
+            .intersect(&up_from(0.5, 0.5), 0.0, f64::INFINITY)
+            .is_none());
+        assert!(one_sided
+            .intersect(&down_from(0.5, 0.5), 0.0, f64::INFINITY)
+            .is_some());
+    }
+
+    #[test]
+    fn quad_bounds_cover_all_corners() {
+        let quad = Quad::new(
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, -1.0),
+            gray(),
+        );
+        let bounds = quad.bounds().unwrap();
+        assert_vec_eq(
+            bounds.min,
+            Vec3::new(1.0, 2.0, 2.0) - Vec3::new(1.0, 1.0, 1.0) * FLAT_MARGIN,
+        );
+        assert_vec_eq(
+            bounds.max,
+            Vec3::new(3.0, 3.0, 3.0) + Vec3::new(1.0, 1.0, 1.0) * FLAT_MARGIN,
+        );
+    }
+
+    #[test]
+    fn disk_is_hit_within_its_radius() {
+        let disk = Disk::new(
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            2.0,
+            gray(),
+        );
+        let hit = disk
+            .intersect(&down_from(2.0, 2.0), 0.0, f64::INFINITY)
+            .unwrap();
+        assert!((hit.t - 5.0).abs() < EPS);
+        assert_vec_eq(hit.point, Vec3::new(2.0, 0.0, 2.0));
+        assert!(disk
+            .intersect(&down_from(3.0, 1.0), 0.0, f64::INFINITY)
+            .is_some());
+        assert!(disk
+            .intersect(&down_from(2.5, 2.5), 0.0, f64::INFINITY)
+            .is_none());
+
+        assert!(disk
+            .intersect(&up_from(1.0, 1.0), 0.0, f64::INFINITY)
+            .is_some());
+        let one_sided = disk.one_sided();
+        assert!(one_sided
+            .intersect(&up_from(1.0, 1.0), 0.0, f64::INFINITY)
+            .is_none());
+    }
+
+    #[test]
+    fn disk_bounds_are_flat_along_the_normal() {
+        let disk = Disk::new(
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            2.0,
+            gray(),
+        );
+        let bounds = disk.bounds().unwrap();
+        let margin = Vec3::new(1.0, 1.0, 1.0) * FLAT_MARGIN;
+        assert_vec_eq(bounds.min, Vec3::new(-1.0, 0.0, 3.0) - margin);
+        assert_vec_eq(bounds.max, Vec3::new(3.0, 4.0, 3.0) + margin);
+
+        // Tilted by 45 degrees around x, the rim reaches radius / sqrt(2) in y and z
+        let disk = Disk::new(Vec3::ZERO, Vec3::new(0.0, 1.0, 1.0), 2.0, gray());
+        let bounds = disk.bounds().unwrap();
+        let reach = 2f64.sqrt();
+        assert_vec_eq(bounds.max, Vec3::new(2.0, reach, reach) + margin);
+        assert_vec_eq(bounds.min, -Vec3::new(2.0, reach, reach) - margin);
+    }
+}
@@ -0,0 +1,1270 @@
+use crate::bvh::Acceleration;
+use crate::canvas::{Framebuffer, Rgb};
+use crate::math::Vec3;
+use crate::object::{Hit, Ray};
+use crate::sampling::{self, Filter, Rng, SamplePattern};
+use crate::scene::{Camera, Light, Scene, Viewport};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+#[derive(Debug, Default)]
+pub struct World {
+    pub scene: Scene,
+    pub viewport: Viewport,
+    pub camera: Camera,
+    /// How rays find the objects they hit, the results are the same either way.
+    ///
+    /// Set with [`World::with_acceleration`] to switch meshes over too.
+    pub acceleration: Acceleration,
+    pub lighting: LightingModel,
+    /// Color of rays that don't hit anything.
+    pub background: Rgb,
+}
+
+/// Which parts of the lighting model are rendered, to isolate one effect or
+/// reproduce the earlier stages of the raytracer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightingModel {
+    /// Light scattered evenly by surfaces facing the light. Ambient light
+    /// is always added.
+    pub diffuse: bool,
+    /// Highlights on shiny surfaces.
+    pub specular: bool,
+    /// Whether objects block the light falling on others, transparent ones
+    /// only dim it.
+    pub shadows: bool,
+    /// Mirror reflections of reflective materials.
+    pub reflections: bool,
+    /// Multiply highlights by the surface color rather than showing them in
+    /// the light's color, as the first versions of the raytracer did.
+    pub tinted_specular: bool,
+}
+
+impl Default for LightingModel {
+    /// Everything on, with highlights in the light's color.
+    fn default() -> Self {
+        LightingModel {
+            diffuse: true,
+            specular: true,
+            shadows: true,
+            reflections: true,
+            tinted_specular: false,
+        }
+    }
+}
+
+impl World {
+    pub fn new(scene: Scene, viewport: Viewport, camera: Camera) -> World {
+        World {
+            scene,
+            viewport,
+            camera,
+            acceleration: Acceleration::default(),
+            lighting: LightingModel::default(),
+            background: Rgb::from_ints(0, 0, 0),
+        }
+    }
+
+    pub fn with_viewport(mut self, viewport: Viewport) -> World {
+        self.viewport = viewport;
+        self
+    }
+
+    pub fn with_camera(mut self, camera: Camera) -> World {
+        self.camera = camera;
+        self
+    }
+
+    /// Switches both the scene's BVH and the ones inside its meshes.
+    pub fn with_acceleration(mut self, acceleration: Acceleration) -> World {
+        self.acceleration = acceleration;
+        self.scene.set_acceleration(acceleration);
+        self
+    }
+
+    pub fn with_lighting(mut self, lighting: LightingModel) -> World {
+        self.lighting = lighting;
+        self
+    }
+
+    pub fn with_background(mut self, background: Rgb) -> World {
+        self.background = background;
+        self
+    }
+
+    /// Color seen along `ray` for `t` in `min_t..=max_t`, following up to
+    /// `depth` reflections and refractions.
+    pub fn trace_ray(&self, ray: &Ray, min_t: f64, max_t: f64, depth: i32) -> Rgb {
+        match self.closest_intersection(ray, min_t, max_t) {
+            Some(hit) => {
+                let material = hit.material;
+                let (p, n) = (hit.point, hit.normal);
+                let v = -ray.direction;
+                let lighting = self.compute_lighting(&p, &n, &v, material.specular, ray.time);
+                let color: Rgb = if self.lighting.tinted_specular {
+                    material
+                        .color
+                        .tint(&lighting.diffuse.add(&lighting.specular))
+                } else {
+                    lighting.shade(&material.color)
+                };
+
+                let r = if self.lighting.reflections {
+                    material.reflective
+                } else {
+                    0.0
+                };
+                let transparency = material.transparency;
+                if depth <= 0 || (r <= 0.0 && transparency <= 0.0) {
+                    return color;
+                }
+
+                let reflected_ray =
+                    Ray::new(p, reflect_vector(&-ray.direction, &n)).with_time(ray.time);
+                let reflected_color =
+                    self.trace_ray(&reflected_ray, 0.0001, f64::INFINITY, depth - 1);
+                let surface = color
+                    .multiply_by(1.0 - r)
+                    .add(&reflected_color.multiply_by(r));
+                if transparency <= 0.0 {
+                    return surface;
+                }
+
+                // Light passing through is split between the reflected and
+                // refracted rays, as the Fresnel equations say
+                let (n1, n2) = if hit.front_face {
+                    (1.0, material.refractive_index)
+                } else {
+                    (material.refractive_index, 1.0)
+                };
+                let passed = match refract(&ray.direction, &n, n1 / n2) {
+                    Some((direction, cos_t)) => {
+                        let cos_i = -ray.direction.normalize().dot(&n);
+                        // Schlick's approximation, on the side of the larger angle
+                        let cos = if n1 > n2 { cos_t } else { cos_i };
+                        let r0 = ((n1 - n2) / (n1 + n2)).powi(2);
+                        let reflectance = r0 + (1.0 - r0) * (1.0 - cos).powi(5);
+
+                        let refracted_ray = Ray::new(p, direction).with_time(ray.time);
+                        let refracted_color =
+                            self.trace_ray(&refracted_ray, 0.0001, f64::INFINITY, depth - 1);
+                        reflected_color
+                            .multiply_by(reflectance)
+                            .add(&refracted_color.multiply_by(1.0 - reflectance))
+                    }
+                    // Total internal reflection
+                    None => reflected_color,
+                };
+                surface
+                    .multiply_by(1.0 - transparency)
+                    .add(&passed.multiply_by(transparency))
+            }
+            None => self.background,
+        }
+    }
+
+    pub fn closest_intersection(&self, ray: &Ray, min_t: f64, max_t: f64) -> Option<Hit<'_>> {
+        let objects = self.scene.objects();
+        let mut closest: Option<Hit> = None;
+        let mut hit = |object: usize, max_t| {
+            let hit = objects[object].intersect(ray, min_t, max_t)?;
+            closest = Some(hit);
+            Some(hit.t)
+        };
+
+        let mut max_t = max_t;
+        match self.acceleration {
+            Acceleration::Bvh => {
+                let bvh = self.scene.bvh();
+                for &object in &bvh.unbounded {
+                    if let Some(t) = hit(object, max_t) {
+                        max_t = t;
+                    }
+                }
+                bvh.bvh
+                    .traverse(ray, min_t, max_t, |i, max_t| hit(bvh.bounded[i], max_t));
+            }
+            Acceleration::BruteForce => {
+                for object in 0..objects.len() {
+                    if let Some(t) = hit(object, max_t) {
+                        max_t = t;
+                    }
+                }
+            }
+        }
+        closest
+    }
+
+    /// Whether any object lies on `ray` in `min_t..=max_t`.
+    pub fn is_occluded(&self, ray: &Ray, min_t: f64, max_t: f64) -> bool {
+        let objects = self.scene.objects();
+        let occludes = |object: usize| objects[object].occludes(ray, min_t, max_t);
+
+        match self.acceleration {
+            Acceleration::Bvh => {
+                let bvh = self.scene.bvh();
+                bvh.unbounded.iter().any(|&object| occludes(object))
+                    || bvh.bvh.any(ray, min_t, max_t, |i| occludes(bvh.bounded[i]))
+            }
+            Acceleration::BruteForce => (0..objects.len()).any(occludes),
+        }
+    }
+
+    /// How much light gets through everything on `ray` in `min_t..=max_t`,
+    /// from `0.0` behind opaque objects to `1.0` when nothing is in the way.
+    ///
+    /// Every surface crossed lets its material's `transparency` through, so
+    /// light is filtered twice by a glass sphere, like rays refracted through
+    /// it are.
+    pub fn transmittance(&self, ray: &Ray, min_t: f64, max_t: f64) -> f64 {
+        // Cheaper to rule out for the many shadow rays with nothing in the way
+        if !self.is_occluded(ray, min_t, max_t) {
+            return 1.0;
+        }
+        let mut transmittance = 1.0;
+        let mut min_t = min_t;
+        while let Some(hit) = self.closest_intersection(ray, min_t, max_t) {
+            transmittance *= hit.material.transparency;
+            if transmittance <= 0.0 {
+                return 0.0;
+            }
+            min_t = hit.t + 0.0001;
+        }
+        transmittance
+    }
+
+    /// Light arriving at point `p` with normal `n`, seen from direction `v`,
+    /// for a surface with specular exponent `s`, at `time` during the frame.
+    pub fn compute_lighting(&self, p: &Vec3, n: &Vec3, v: &Vec3, s: i32, time: f64) -> Lighting {
+        let mut lighting = Lighting::default();
+        for (index, light) in self.scene.lights.iter().enumerate() {
+            let radiance = light.radiance();
+            match light {
+                Light::AmbientL(_) => {
+                    lighting.diffuse = lighting.diffuse.add(&radiance);
+                }
+                Light::AreaL(al) => {
+                    // Seeded by the point, so the noise doesn't depend on
+                    // which thread renders it
+                    let seed = [p.x.to_bits(), p.y.to_bits(), p.z.to_bits(), index as u64];
+                    let mut rng = Rng::new(sampling::hash(&seed));
+                    let radiance = radiance.multiply_by(1.0 / al.samples as f64);
+                    for sample in 0..al.samples {
+                        let uv = sampling::stratified(sample, al.samples, &mut rng);
+                        let l = al.shape.sample(p, uv) - *p;
+                        let radiance = radiance.multiply_by(al.attenuation.factor(l.length()));
+                        let shadow = Ray::new(*p, l).with_time(time);
+                        self.add_light(&mut lighting, n, v, s, &shadow, &radiance, 1.0);
+                    }
+                }
+                other => {
+                    // Direction to the light, how much of it reaches `p`, and
+                    // how far along `l` shadows can be cast from
+                    let (l, falloff, t_max) = match other {
+                        Light::PointL(pl) => {
+                            let l = pl.position - *p;
+                            (l, pl.attenuation.factor(l.length()), 1.0)
+                        }
+                        Light::SpotL(sl) => {
+                            let l = sl.position - *p;
+                            let falloff = sl.attenuation.factor(l.length()) * sl.cone_factor(&-l);
+                            (l, falloff, 1.0)
+                        }
+                        Light::DirectionalL(dl) => (dl.direction, 1.0, f64::INFINITY),
+                        _ => (Vec3::ZERO, 0.0, 0.0),
+                    };
+                    if falloff > 0.0 {
+                        let radiance = radiance.multiply_by(falloff);
+                        let shadow = Ray::new(*p, l).with_time(time);
+                        self.add_light(&mut lighting, n, v, s, &shadow, &radiance, t_max);
+                    }
+                }
+            }
+        }
+        lighting
+    }
+
+    /// Adds the light coming along `shadow`, from the lit point towards the
+    /// light, to `lighting`, dimmed by whatever it passes through closer than
+    /// `t_max`.
+    ///
+    /// `shadow` reaches positioned lights at `t = 1`, anything behind them
+    /// can't block their light.
+    #[allow(clippy::too_many_arguments)]
+    fn add_light(
+        &self,
+        lighting: &mut Lighting,
+        n: &Vec3,
+        v: &Vec3,
+        s: i32,
+        shadow: &Ray,
+        radiance: &Rgb,
+        t_max: f64,
+    ) {
+        let radiance = &if self.lighting.shadows {
+            radiance.multiply_by(self.transmittance(shadow, 0.0001, t_max))
+        } else {
+            *radiance
+        };
+        if *radiance == Rgb::default() {
+            return;
+        }
+        let l = &shadow.direction;
+
+        let n_dot_l: f64 = n.dot(l);
+        if self.lighting.diffuse && n_dot_l > 0.0 {
+            let i = n_dot_l / (n.length() * l.length());
+            lighting.diffuse = lighting.diffuse.add(&radiance.multiply_by(i));
+        }
+
+        if self.lighting.specular && s != -1 {
+            let r: Vec3 = reflect_vector(l, n);
+            let r_dot_v: f64 = r.dot(v);
+            if r_dot_v > 0.0 {
+                let i = (r_dot_v / (r.length() * v.length())).powf(s as f64);
+                lighting.specular = lighting.specular.add(&radiance.multiply_by(i));
+            }
+        }
+    }
+}
+
+/// Light arriving at a point, per channel, with `255` for a white light of
+/// intensity `1.0`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lighting {
+    /// Light scattered evenly, which takes on the surface's color.
+    pub diffuse: Rgb,
+    /// Highlights, which keep the color of the light.
+    pub specular: Rgb,
+}
+
+impl Lighting {
+    /// The color of a surface of color `color` under this light.
+    pub fn shade(&self, color: &Rgb) -> Rgb {
+        color.tint(&self.diffuse).add(&self.specular)
+    }
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Lighting {
+            diffuse: Rgb::from_ints(0, 0, 0),
+            specular: Rgb::from_ints(0, 0, 0),
+        }
+    }
+}
+
+/// Mirrors `r` around the normal `n`.
+pub fn reflect_vector(r: &Vec3, n: &Vec3) -> Vec3 {
+    *n * (n.dot(r) * 2.0) - *r
+}
+
+/// Bends direction `d` through a surface with unit normal `n` facing against
+/// it, by Snell's law. `eta` is the ratio of the refractive indices, the one
+/// `d` comes from over the one it enters.
+///
+/// Returns the unit refracted direction and the cosine of its angle to `-n`,
+/// or `None` on total internal reflection.
+pub fn refract(d: &Vec3, n: &Vec3, eta: f64) -> Option<(Vec3, f64)> {
+    let d = d.normalize();
+    let cos_i = -d.dot(n);
+    let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+    if sin2_t > 1.0 {
+        return None;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    Some((d * eta + *n * (eta * cos_i - cos_t), cos_t))
+}
+
+const TILE_SIZE: i32 = 32;
+
+/// A rectangle of canvas coordinates, `x0..x1` by `y0..y1`.
+struct Tile {
+    x0: i32,
+    x1: i32,
+    y0: i32,
+    y1: i32,
+}
+
+impl Tile {
+    /// Where `(x, y)` goes in a row-major buffer of the tile's pixels.
+    fn index(&self, x: i32, y: i32) -> usize {
+        (y - self.y0) as usize * (self.x1 - self.x0) as usize + (x - self.x0) as usize
+    }
+}
+
+/// How [`render`] turns a world into pixels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderSettings {
+    /// How many reflections and refractions rays follow.
+    pub depth: i32,
+    pub threads: usize,
+    /// Rays per pixel, `1` disables anti-aliasing.
+    pub samples: usize,
+    pub pattern: SamplePattern,
+    /// How the samples of a pixel are averaged.
+    pub filter: Filter,
+    /// Refine only where it shows instead, replacing `samples`, `pattern`
+    /// and `filter`.
+    pub adaptive: Option<Adaptive>,
+}
+
+impl Default for RenderSettings {
+    /// One ray per pixel, three bounces, on every core.
+    fn default() -> Self {
+        RenderSettings {
+            depth: 3,
+            threads: num_cpus::get(),
+            samples: 1,
+            pattern: SamplePattern::default(),
+            filter: Filter::default(),
+            adaptive: None,
+        }
+    }
+}
+
+/// Adaptive anti-aliasing.
+///
+/// Every pixel gets a single ray first. Pixels that differ from one of their
+/// eight neighbours by more than `threshold` are then split into four
+/// quarters with a ray each, and quarters that still differ from each other
+/// are split again, up to `max_depth` times.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Adaptive {
+    /// Largest difference in any channel, as a fraction of `255`, that is
+    /// left alone.
+    pub threshold: f64,
+    /// How often a pixel may be split, at most `4^max_depth` rays per pixel.
+    pub max_depth: u32,
+}
+
+impl Default for Adaptive {
+    fn default() -> Self {
+        Adaptive {
+            threshold: 0.1,
+            max_depth: 2,
+        }
+    }
+}
+
+/// What [`render`] spent on an image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    /// Rays traced from the camera, including `extra_samples`.
+    pub samples: usize,
+    /// Rays adaptive anti-aliasing added to refine pixels.
+    pub extra_samples: usize,
+    /// Pixels adaptive anti-aliasing refined.
+    pub refined_pixels: usize,
+}
+
+/// How different two colors look, the largest difference in any channel
+/// as a fraction of `255`.
+fn contrast(a: &Rgb, b: &Rgb) -> f64 {
+    let red = (a.red - b.red).abs();
+    let green = (a.green - b.green).abs();
+    let blue = (a.blue - b.blue).abs();
+    red.max(green).max(blue) / 255.0
+}
+
+/// Random numbers for pixel `(x, y)`, seeded by the pixel so the noise
+/// doesn't depend on the thread count. `pass` tells apart the passes that
+/// sample the same pixel.
+fn pixel_rng(x: i32, y: i32, pass: u64) -> Rng {
+    Rng::new(sampling::hash(&[x as u64, y as u64, pass]))
+}
+
+impl World {
+    /// The color seen through point `(x, y)` of the centered canvas.
+    fn trace_canvas(
+        &self,
+        width: usize,
+        height: usize,
+        x: f64,
+        y: f64,
+        depth: i32,
+        rng: &mut Rng,
+    ) -> Rgb {
+        match self.camera.ray(&self.viewport, width, height, x, y, rng) {
+            Some(ray) => self.trace_ray(&ray, 1.0, f64::INFINITY, depth),
+            None => Rgb::from_ints(0, 0, 0),
+        }
+    }
+
+    /// The color of the centered canvas pixel `(x, y)`, averaging samples
+    /// spread over the filter's reach around it, and the number of samples.
+    fn render_pixel(
+        &self,
+        width: usize,
+        height: usize,
+        x: i32,
+        y: i32,
+        settings: &RenderSettings,
+    ) -> (Rgb, usize) {
+        let mut rng = pixel_rng(x, y, 0);
+        let trace = |x: f64, y: f64, rng: &mut Rng| {
+            self.trace_canvas(width, height, x, y, settings.depth, rng)
+        };
+        if settings.samples <= 1 {
+            return (trace(x as f64, y as f64, &mut rng), 1);
+        }
+
+        let radius = settings.filter.radius();
+        let (mut sum, mut total_weight, mut samples) = (Rgb::from_ints(0, 0, 0), 0.0, 0);
+        for (u, v) in settings.pattern.points(settings.samples, &mut rng) {
+            let (dx, dy) = ((2.0 * u - 1.0) * radius, (2.0 * v - 1.0) * radius);
+            let weight = settings.filter.weight(dx, dy);
+            if weight == 0.0 {
+                continue;
+            }
+            let color = trace(x as f64 + dx, y as f64 + dy, &mut rng);
+            sum = sum.add(&color.multiply_by(weight));
+            total_weight += weight;
+            samples += 1;
+        }
+        if total_weight.abs() < 1e-9 {
+            // Only possible with negative lobes, fall back to the pixel center
+            return (trace(x as f64, y as f64, &mut rng), samples + 1);
+        }
+        (sum.multiply_by(1.0 / total_weight), samples)
+    }
+
+    /// The average color of the square of side `size` around `(x, y)`,
+    /// splitting it further while its quarters differ, and the number of
+    /// samples that took.
+    #[allow(clippy::too_many_arguments)]
+    fn refine(
+        &self,
+        width: usize,
+        height: usize,
+        (x, y): (f64, f64),
+        size: f64,
+        level: u32,
+        depth: i32,
+        adaptive: &Adaptive,
+        rng: &mut Rng,
+    ) -> (Rgb, usize) {
+        let offset = size / 4.0;
+        let centers = [
+            (x - offset, y - offset),
+            (x + offset, y - offset),
+            (x - offset, y + offset),
+            (x + offset, y + offset),
+        ];
+        let mut colors = centers.map(|(x, y)| self.trace_canvas(width, height, x, y, depth, rng));
+        let mut samples = 4;
+
+        let uneven = colors
+            .iter()
+            .any(|a| colors.iter().any(|b| contrast(a, b) > adaptive.threshold));
+        if uneven && level < adaptive.max_depth {
+            for (color, &center) in colors.iter_mut().zip(&centers) {
+                let (refined, spent) = self.refine(
+                    width,
+                    height,
+                    center,
+                    size / 2.0,
+                    level + 1,
+                    depth,
+                    adaptive,
+                    rng,
+                );
+                *color = refined;
+                samples += spent;
+            }
+        }
+
+        let sum = colors
+            .iter()
+            .fold(Rgb::from_ints(0, 0, 0), |sum, color| sum.add(color));
+        (sum.multiply_by(0.25), samples)
+    }
+}
+
+/// Runs `pixel` for every pixel of `tiles` on `threads` workers, returning
+/// the results in the row-major order of `area`.
+///
+/// Workers pull the next unrendered tile from a shared counter, so fast tiles
+/// (mostly background) don't leave threads idle while others are still busy.
+fn render_tiles(
+    area: &Tile,
+    tiles: &[Tile],
+    threads: usize,
+    pixel: impl Fn(i32, i32) -> (Rgb, usize) + Sync,
+) -> Vec<(Rgb, usize)> {
+    let width = (area.x1 - area.x0) as usize;
+    let height = (area.y1 - area.y0) as usize;
+    let mut image = vec![(Rgb::from_ints(0, 0, 0), 0); width * height];
+
+    let next_tile = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            let sender = sender.clone();
+            let (next_tile, pixel) = (&next_tile, &pixel);
+            scope.spawn(move || {
+                while let Some(tile) = tiles.get(next_tile.fetch_add(1, Ordering::Relaxed)) {
+                    let mut colors = Vec::with_capacity((TILE_SIZE * TILE_SIZE) as usize);
+                    for y in tile.y0..tile.y1 {
+                        for x in tile.x0..tile.x1 {
+                            colors.push(pixel(x, y));
+                        }
+                    }
+                    if sender.send((tile, colors)).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        for (tile, colors) in receiver {
+            let mut colors = colors.into_iter();
+            for y in tile.y0..tile.y1 {
+                for x in tile.x0..tile.x1 {
+                    image[area.index(x, y)] = colors.next().unwrap();
+                }
+            }
+        }
+    });
+    image
+}
+
+/// Renders `world` into `canvas`, spreading square tiles over the threads of
+/// `settings`.
+pub fn render(
+    world: &World,
+    canvas: &mut impl Framebuffer,
+    settings: &RenderSettings,
+) -> RenderStats {
+    let (width, height) = (canvas.width(), canvas.height());
+    // Same centered range `put_pixel` accepts, also for odd sizes
+    let area = Tile {
+        x0: -(width as i32) / 2,
+        x1: width as i32 - width as i32 / 2,
+        y0: height as i32 / 2 - height as i32,
+        y1: height as i32 / 2,
+    };
+
+    let mut tiles = Vec::new();
+    for y0 in (area.y0..area.y1).step_by(TILE_SIZE as usize) {
+        for x0 in (area.x0..area.x1).step_by(TILE_SIZE as usize) {
+            tiles.push(Tile {
+                x0,
+                x1: i32::min(x0 + TILE_SIZE, area.x1),
+                y0,
+                y1: i32::min(y0 + TILE_SIZE, area.y1),
+            });
+        }
+    }
+
+    let mut stats = RenderStats::default();
+    let image = match &settings.adaptive {
+        None => render_tiles(&area, &tiles, settings.threads, |x, y| {
+            world.render_pixel(width, height, x, y, settings)
+        }),
+        Some(adaptive) => {
+            let first = render_tiles(&area, &tiles, settings.threads, |x, y| {
+                let mut rng = pixel_rng(x, y, 0);
+                let (cx, cy) = (x as f64, y as f64);
+                let color = world.trace_canvas(width, height, cx, cy, settings.depth, &mut rng);
+                (color, 1)
+            });
+            stats.samples = first.len();
+
+            let at = |x: i32, y: i32| {
+                let inside = (area.x0..area.x1).contains(&x) && (area.y0..area.y1).contains(&y);
+                inside.then(|| &first[area.index(x, y)].0)
+            };
+            let refined = render_tiles(&area, &tiles, settings.threads, |x, y| {
+                let color = at(x, y).unwrap();
+                let neighbours = (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)));
+                let uneven = neighbours
+                    .filter_map(|(dx, dy)| at(x + dx, y + dy))
+                    .any(|neighbour| contrast(color, neighbour) > adaptive.threshold);
+                if !uneven || adaptive.max_depth == 0 {
+                    return (*color, 0);
+                }
+                let center = (x as f64, y as f64);
+                let mut rng = pixel_rng(x, y, 1);
+                world.refine(
+                    width,
+                    height,
+                    center,
+                    1.0,
+                    1,
+                    settings.depth,
+                    adaptive,
+                    &mut rng,
+                )
+            });
+            stats.extra_samples = refined.iter().map(|&(_, samples)| samples).sum();
+            stats.refined_pixels = refined.iter().filter(|&&(_, samples)| samples > 0).count();
+            refined
+        }
+    };
+    stats.samples += image.iter().map(|&(_, samples)| samples).sum::<usize>();
+
+    for y in area.y0..area.y1 {
+        for x in area.x0..area.x1 {
+            canvas.put_pixel(x, y, &image[area.index(x, y)].0);
+        }
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::HdrCanvas;
+    use crate::mesh::Mesh;
+    use crate::object::{Moving, Sphere, WithMaterial};
+    use crate::planar::{Disk, Plane, Quad};
+
+    /// A surface at the origin facing a light 5 units away, with a sphere
+    /// 3 units behind the light.
+    fn world_with_light_between(light: Light) -> World {
+        let scene = Scene::new()
+            .with_object(Sphere::new(
+                Vec3::new(0.0, 0.0, 8.0),
+                1.0,
+                Rgb::from_ints(255, 255, 255),
+            ))
+            .with_light(light);
+        World::new(scene, Viewport::default(), Camera::default())
+    }
+
+    fn diffuse_at_origin(world: &World) -> Rgb {
+        let n = Vec3::new(0.0, 0.0, 1.0);
+        world.compute_lighting(&Vec3::ZERO, &n, &n, -1, 0.0).diffuse
+    }
+
+    #[test]
+    fn objects_behind_a_point_light_cast_no_shadow() {
+        let world = world_with_light_between(Light::point(1.0, Vec3::new(0.0, 0.0, 5.0)));
+        assert_eq!(diffuse_at_origin(&world), Rgb::from_ints(255, 255, 255));
+    }
+
+    #[test]
+    fn objects_behind_a_spotlight_cast_no_shadow() {
+        let light = Light::spot(
+            1.0,
+            Vec3::new(0.0, 0.0, 5.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            0.5,
+            1.0,
+        );
+        let world = world_with_light_between(light);
+        assert_eq!(diffuse_at_origin(&world), Rgb::from_ints(255, 255, 255));
+    }
+
+    #[test]
+    fn objects_between_a_point_light_and_the_surface_cast_a_shadow() {
+        let world = world_with_light_between(Light::point(1.0, Vec3::new(0.0, 0.0, 10.0)));
+        assert_eq!(diffuse_at_origin(&world), Rgb::from_ints(0, 0, 0));
+    }
+
+    #[test]
+    fn directional_lights_are_blocked_at_any_distance() {
+        let world = world_with_light_between(Light::directional(1.0, Vec3::new(0.0, 0.0, 1.0)));
+        assert_eq!(diffuse_at_origin(&world), Rgb::from_ints(0, 0, 0));
+    }
+
+    fn world_with_glass_between(transparency: f64) -> World {
+        let glass = Sphere::new(Vec3::new(0.0, 0.0, 8.0), 1.0, Rgb::from_ints(255, 255, 255))
+            .with_transparency(transparency)
+            .with_refractive_index(1.5);
+        let scene = Scene::new()
+            .with_object(glass)
+            .with_light(Light::point(1.0, Vec3::new(0.0, 0.0, 10.0)));
+        World::new(scene, Viewport::default(), Camera::default())
+    }
+
+    #[test]
+    fn transparent_objects_cast_lighter_shadows() {
+        let clear = world_with_glass_between(1.0);
+        assert_eq!(diffuse_at_origin(&clear), Rgb::from_ints(255, 255, 255));
+
+        // Through both sides of the sphere
+        let half = world_with_glass_between(0.5);
+        assert_eq!(
+            diffuse_at_origin(&half),
+            Rgb::from_ints(255, 255, 255).multiply_by(0.25)
+        );
+
+        let opaque = world_with_glass_between(0.0);
+        assert_eq!(diffuse_at_origin(&opaque), Rgb::from_ints(0, 0, 0));
+    }
+
+    #[test]
+    fn transmittance_multiplies_every_surface_on_the_way() {
+        let pane = |z: f64, transparency: f64| {
+            Quad::new(
+                Vec3::new(-1.0, -1.0, z),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(0.0, 2.0, 0.0),
+                Rgb::from_ints(255, 255, 255),
+            )
+            .with_transparency(transparency)
+        };
+        let scene = Scene::new()
+            .with_object(pane(1.0, 0.5))
+            .with_object(pane(2.0, 0.8))
+            .with_object(pane(3.0, 0.0));
+        let world = World::new(scene, Viewport::default(), Camera::default());
+        let ray = Ray::new(Vec3::ZERO, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(world.transmittance(&ray, 0.0, 0.5), 1.0);
+        assert_eq!(world.transmittance(&ray, 0.0, 1.5), 0.5);
+        assert!((world.transmittance(&ray, 0.0, 2.5) - 0.4).abs() < 1e-12);
+        assert_eq!(world.transmittance(&ray, 0.0, 3.5), 0.0);
+        assert!((world.transmittance(&ray, 1.5, 2.5) - 0.8).abs() < 1e-12);
+    }
+
+    /// A 2 by 2 area light at height 10 over the `z = 0` plane, with or
+    /// without a wall at height 5 over the half with `x < 0`.
+    ///
+    /// Seen from `(x, 0, 0)`, the wall hides the part of the light with
+    /// `x_light < -x`, so the penumbra runs from `x = -1` to `x = 1`.
+    fn world_under_area_light(samples: usize, wall: bool) -> World {
+        let light = Light::rect(
+            1.0,
+            Vec3::new(-1.0, -1.0, 10.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+        )
+        .with_samples(samples);
+        let mut scene = Scene::new().with_light(light);
+        if wall {
+            scene = scene.with_object(Quad::new(
+                Vec3::new(-10.0, -10.0, 5.0),
+                Vec3::new(10.0, 0.0, 0.0),
+                Vec3::new(0.0, 20.0, 0.0),
+                Rgb::from_ints(255, 255, 255),
+            ));
+        }
+        World::new(scene, Viewport::default(), Camera::default())
+    }
+
+    /// Red channel of the diffuse light at `(x, 0, 0)` on the `z = 0` plane.
+    fn diffuse_along_x(world: &World, x: f64) -> f64 {
+        let n = Vec3::new(0.0, 0.0, 1.0);
+        world
+            .compute_lighting(&Vec3::new(x, 0.0, 0.0), &n, &n, -1, 0.0)
+            .diffuse
+            .red
+    }
+
+    #[test]
+    fn half_hidden_area_lights_cast_a_penumbra() {
+        let open = world_under_area_light(16, false);
+        let walled = world_under_area_light(16, true);
+
+        assert_eq!(diffuse_along_x(&walled, -1.5), 0.0);
+        assert_eq!(diffuse_along_x(&walled, 1.5), diffuse_along_x(&open, 1.5));
+
+        let half = diffuse_along_x(&walled, 0.0) / diffuse_along_x(&open, 0.0);
+        assert!(half > 0.35 && half < 0.65, "{}", half);
+        let quarter = diffuse_along_x(&walled, -0.5) / diffuse_along_x(&open, -0.5);
+        assert!(quarter > 0.1 && quarter < 0.4, "{}", quarter);
+    }
+
+    #[test]
+    fn area_lights_send_as_many_shadow_rays_as_samples() {
+        // One sample is either blocked or not, so its shadow is hard
+        let open = world_under_area_light(1, false);
+        let walled = world_under_area_light(1, true);
+        for step in 0..=20 {
+            let x = -1.0 + step as f64 / 10.0;
+            let lit = diffuse_along_x(&walled, x);
+            assert!(lit == 0.0 || lit == diffuse_along_x(&open, x), "{}", x);
+        }
+
+        // Seen from x, the wall hides the columns of samples left of -x.
+        // Where that is a column boundary, exactly that share of the samples
+        // is hidden, give or take their slightly different angles
+        for &(samples, x, hidden) in &[
+            (4, 0.0, 2.0 / 4.0),
+            (9, 1.0 / 3.0, 3.0 / 9.0),
+            (9, -1.0 / 3.0, 6.0 / 9.0),
+            (16, -0.5, 12.0 / 16.0),
+            (16, 0.5, 4.0 / 16.0),
+        ] {
+            let open = world_under_area_light(samples, false);
+            let walled = world_under_area_light(samples, true);
+            let lit = diffuse_along_x(&walled, x) / diffuse_along_x(&open, x);
+            assert!(
+                (lit - (1.0 - hidden)).abs() < 0.03,
+                "{} {} {}",
+                samples,
+                x,
+                lit
+            );
+        }
+    }
+
+    /// The color seen looking from the origin straight at a sphere of
+    /// `color` at `(0, 0, 5)`, lit by `lights`.
+    fn color_facing(color: Rgb, specular: i32, lights: Vec<Light>) -> Rgb {
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, 5.0), 1.0, color).with_specular(specular);
+        let mut scene = Scene::new().with_object(sphere);
+        for light in lights {
+            scene = scene.with_light(light);
+        }
+        let world = World::new(scene, Viewport::default(), Camera::default());
+        let ray = Ray::new(Vec3::ZERO, Vec3::new(0.0, 0.0, 1.0));
+        world.trace_ray(&ray, 1.0, f64::INFINITY, 0)
+    }
+
+    #[test]
+    fn colored_lights_light_each_channel_separately() {
+        let white = Rgb::from_ints(255, 255, 255);
+        let red_light = Light::point(1.0, Vec3::ZERO).with_color(Rgb::from_ints(255, 0, 0));
+        assert_eq!(
+            color_facing(white, -1, vec![red_light.clone()]),
+            Rgb::from_ints(255, 0, 0)
+        );
+
+        // Channels of several lights add up
+        let green_ambient = Light::ambient(0.5).with_color(Rgb::from_ints(0, 255, 0));
+        assert_eq!(
+            color_facing(white, -1, vec![red_light.clone(), green_ambient]),
+            Rgb {
+                red: 255.0,
+                green: 127.5,
+                blue: 0.0,
+            }
+        );
+
+        // A surface reflects nothing of a light it has none of the color of
+        assert_eq!(
+            color_facing(Rgb::from_ints(0, 255, 255), -1, vec![red_light]),
+            Rgb::from_ints(0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn highlights_take_the_color_of_the_light() {
+        // Seen head on, both the diffuse and the specular term are 1
+        let blue = Rgb::from_ints(0, 0, 255);
+        let white_light = Light::point(1.0, Vec3::ZERO);
+        assert_eq!(
+            color_facing(blue, 10, vec![white_light.clone()]),
+            Rgb::from_ints(255, 255, 510)
+        );
+
+        let red_light = white_light.with_color(Rgb::from_ints(255, 0, 0));
+        assert_eq!(
+            color_facing(Rgb::from_ints(255, 255, 255), 10, vec![red_light]),
+            Rgb::from_ints(510, 0, 0)
+        );
+    }
+
+    #[test]
+    fn tinted_highlights_take_the_color_of_the_surface() {
+        let sphere =
+            Sphere::new(Vec3::new(0.0, 0.0, 5.0), 1.0, Rgb::from_ints(0, 0, 255)).with_specular(10);
+        let scene = Scene::new()
+            .with_object(sphere)
+            .with_light(Light::point(1.0, Vec3::ZERO));
+        let world = World::new(scene, Viewport::default(), Camera::default()).with_lighting(
+            LightingModel {
+                tinted_specular: true,
+                ..LightingModel::default()
+            },
+        );
+        let ray = Ray::new(Vec3::ZERO, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(
+            world.trace_ray(&ray, 1.0, f64::INFINITY, 0),
+            Rgb::from_ints(0, 0, 510)
+        );
+    }
+
+    const INTO_GLASS: f64 = 1.0 / 1.5;
+
+    /// A direction at `angle` to the normal `(0, 0, -1)` of a surface facing
+    /// `-z`, heading into it.
+    fn incoming(angle: f64) -> Vec3 {
+        Vec3::new(angle.sin(), 0.0, angle.cos())
+    }
+
+    #[test]
+    fn refraction_at_normal_incidence_goes_straight_through() {
+        let n = Vec3::new(0.0, 0.0, -1.0);
+        let (direction, cos_t) = refract(&Vec3::new(0.0, 0.0, 3.0), &n, INTO_GLASS).unwrap();
+        assert!((direction - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+        assert!((cos_t - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn refraction_follows_snells_law() {
+        let n = Vec3::new(0.0, 0.0, -1.0);
+        let angle = 45f64.to_radians();
+        let (direction, cos_t) = refract(&(incoming(angle) * 2.0), &n, INTO_GLASS).unwrap();
+
+        // n1 sin(i) = n2 sin(t)
+        let sin_t = angle.sin() / 1.5;
+        assert!((direction.length() - 1.0).abs() < 1e-12);
+        assert!((direction - incoming(sin_t.asin())).length() < 1e-12);
+        assert!((cos_t - (1.0 - sin_t * sin_t).sqrt()).abs() < 1e-12);
+
+        // Leaving the glass bends it back
+        let (back, _) = refract(&direction, &n, 1.5).unwrap();
+        assert!((back - incoming(angle)).length() < 1e-12);
+    }
+
+    #[test]
+    fn refraction_out_of_glass_beyond_the_critical_angle_is_total_internal_reflection() {
+        let n = Vec3::new(0.0, 0.0, -1.0);
+        let critical = (1.0f64 / 1.5).asin();
+        assert!(refract(&incoming(critical - 0.01), &n, 1.5).is_some());
+        assert!(refract(&incoming(critical + 0.01), &n, 1.5).is_none());
+        assert!(refract(&incoming(80f64.to_radians()), &n, 1.5).is_none());
+        // Entering a denser medium never reflects totally
+        assert!(refract(&incoming(89f64.to_radians()), &n, INTO_GLASS).is_some());
+    }
+
+    /// A wavy grid of `n` by `n` squares, two triangles each.
+    fn terrain(n: usize, color: Rgb) -> Mesh {
+        let mut positions = Vec::new();
+        for i in 0..=n {
+            for j in 0..=n {
+                let (x, z) = (i as f64 - n as f64 / 2.0, j as f64 - n as f64 / 2.0);
+                positions.push(Vec3::new(x, (x * 0.7).sin() * (z * 0.5).cos(), z));
+            }
+        }
+        let mut triangles = Vec::new();
+        for i in 0..n {
+            for j in 0..n {
+                let corner = i * (n + 1) + j;
+                triangles.push([corner, corner + 1, corner + n + 2]);
+                triangles.push([corner, corner + n + 2, corner + n + 1]);
+            }
+        }
+        Mesh::new(positions, triangles, color).with_smooth_normals()
+    }
+
+    fn mixed_scene() -> Scene {
+        let color = |i: i16| Rgb::from_ints(i, 255 - i, 100);
+        let mut scene = Scene::new()
+            .with_object(Plane::new(
+                Vec3::new(0.0, -3.0, 0.0),
+                Vec3::new(0.1, 1.0, 0.0),
+                color(0),
+            ))
+            .with_object(terrain(8, color(10)))
+            .with_object(Moving::new(terrain(4, color(20)), Vec3::new(0.0, 2.0, 0.0)))
+            .with_object(Quad::new(
+                Vec3::new(-5.0, -2.0, 5.0),
+                Vec3::new(10.0, 0.0, 0.0),
+                Vec3::new(0.0, 6.0, 0.0),
+                color(30),
+            ))
+            .with_object(
+                Disk::new(
+                    Vec3::new(-5.0, 0.0, 0.0),
+                    Vec3::new(1.0, 0.0, 0.2),
+                    2.0,
+                    color(40),
+                )
+                .one_sided(),
+            );
+        let mut rng = Rng::new(7);
+        for i in 0..20 {
+            let mut coordinate = || rng.next_f64() * 8.0 - 4.0;
+            let center = Vec3::new(coordinate(), coordinate(), coordinate());
+            scene.add_object(Sphere::new(center, 0.6, color(50 + i)));
+        }
+        scene
+    }
+
+    #[test]
+    fn bvh_and_brute_force_find_the_same_hits() {
+        let world = World::new(mixed_scene(), Viewport::default(), Camera::default());
+        let brute_force = World::new(mixed_scene(), Viewport::default(), Camera::default())
+            .with_acceleration(Acceleration::BruteForce);
+
+        let mut rng = Rng::new(42);
+        let mut random = |scale: f64| {
+            let mut coordinate = || (rng.next_f64() * 2.0 - 1.0) * scale;
+            Vec3::new(coordinate(), coordinate(), coordinate())
+        };
+        let mut mesh_hits = 0;
+        for i in 0..5000 {
+            let ray = Ray::new(random(8.0), random(1.0)).with_time((i % 4) as f64 / 3.0);
+            let expected = brute_force.closest_intersection(&ray, 0.001, f64::INFINITY);
+            let actual = world.closest_intersection(&ray, 0.001, f64::INFINITY);
+            match (expected, actual) {
+                (Some(expected), Some(actual)) => {
+                    assert!((expected.t - actual.t).abs() < 1e-9, "{:?}", ray);
+                    assert_eq!(expected.material, actual.material, "{:?}", ray);
+                    assert_eq!(expected.normal, actual.normal, "{:?}", ray);
+                    let color = expected.material.color;
+                    if color.red == 10.0 || color.red == 20.0 {
+                        mesh_hits += 1;
+                    }
+                }
+                (None, None) => {}
+                (expected, actual) => {
+                    panic!(
+                        "{:?}: {:?} != {:?}",
+                        ray,
+                        expected.map(|h| h.t),
+                        actual.map(|h| h.t)
+                    )
+                }
+            }
+            assert_eq!(
+                brute_force.is_occluded(&ray, 0.001, 5.0),
+                world.is_occluded(&ray, 0.001, 5.0),
+                "{:?}",
+                ray
+            );
+        }
+        assert!(mesh_hits > 100, "only {} rays hit a mesh", mesh_hits);
+    }
+
+    /// A white sphere in the middle of a black background.
+    fn sphere_world() -> World {
+        let scene = Scene::new()
+            .with_object(Sphere::new(
+                Vec3::new(0.0, 0.0, 5.0),
+                1.5,
+                Rgb::from_ints(255, 255, 255),
+            ))
+            .with_light(Light::ambient(1.0));
+        World::new(scene, Viewport::default(), Camera::default())
+    }
+
+    fn render_adaptive(
+        world: &World,
+        adaptive: Adaptive,
+        threads: usize,
+    ) -> (HdrCanvas, RenderStats) {
+        let mut canvas = HdrCanvas::new(16, 16);
+        let settings = RenderSettings {
+            threads,
+            adaptive: Some(adaptive),
+            ..RenderSettings::default()
+        };
+        let stats = render(world, &mut canvas, &settings);
+        (canvas, stats)
+    }
+
+    #[test]
+    fn adaptive_leaves_flat_images_alone() {
+        let world = World::new(Scene::new(), Viewport::default(), Camera::default())
+            .with_background(Rgb::from_ints(20, 40, 60));
+        let adaptive = Adaptive {
+            threshold: 0.0,
+            max_depth: 3,
+        };
+        let (canvas, stats) = render_adaptive(&world, adaptive, 2);
+        assert_eq!(
+            stats,
+            RenderStats {
+                samples: 256,
+                extra_samples: 0,
+                refined_pixels: 0,
+            }
+        );
+        assert!(canvas
+            .buffer()
+            .iter()
+            .all(|&c| c == Rgb::from_ints(20, 40, 60)));
+    }
+
+    #[test]
+    fn adaptive_refines_only_the_edges() {
+        let world = sphere_world();
+        let (canvas, stats) = render_adaptive(&world, Adaptive::default(), 2);
+
+        assert!(
+            stats.refined_pixels > 0 && stats.refined_pixels < 256 / 2,
+            "{:?}",
+            stats
+        );
+        assert_eq!(stats.samples, 256 + stats.extra_samples);
+        // Four rays per split, at most 4 + 16 for two levels
+        assert!(
+            stats.extra_samples >= 4 * stats.refined_pixels,
+            "{:?}",
+            stats
+        );
+        assert!(
+            stats.extra_samples <= 20 * stats.refined_pixels,
+            "{:?}",
+            stats
+        );
+
+        // The middle of the sphere and the corners stay as they were
+        assert_eq!(canvas.buffer()[8 * 16 + 8], Rgb::from_ints(255, 255, 255));
+        assert_eq!(canvas.buffer()[0], Rgb::from_ints(0, 0, 0));
+        // Edge pixels are blended
+        assert!(canvas.buffer().iter().any(|c| c.red > 0.0 && c.red < 255.0));
+    }
+
+    #[test]
+    fn adaptive_stops_at_max_depth_zero_and_high_thresholds() {
+        let world = sphere_world();
+        for &adaptive in &[
+            Adaptive {
+                threshold: 0.1,
+                max_depth: 0,
+            },
+            Adaptive {
+                threshold: 1.0,
+                max_depth: 2,
+            },
+        ] {
+            let (_, stats) = render_adaptive(&world, adaptive, 2);
+            assert_eq!(stats.extra_samples, 0, "{:?}", adaptive);
+            assert_eq!(stats.refined_pixels, 0, "{:?}", adaptive);
+        }
+    }
+
+    #[test]
+    fn adaptive_results_dont_depend_on_the_thread_count() {
+        let world = sphere_world();
+        let (one, one_stats) = render_adaptive(&world, Adaptive::default(), 1);
+        let (four, four_stats) = render_adaptive(&world, Adaptive::default(), 4);
+        assert_eq!(one.buffer(), four.buffer());
+        assert_eq!(one_stats, four_stats);
+    }
+
+    fn render_small(world: &World) -> HdrCanvas {
+        let mut canvas = HdrCanvas::new(16, 16);
+        let settings = RenderSettings {
+            samples: 4,
+            threads: 2,
+            ..RenderSettings::default()
+        };
+        render(world, &mut canvas, &settings);
+        canvas
+    }
+
+    #[test]
+    fn static_objects_look_the_same_with_the_shutter_open() {
+        let still = sphere_world();
+        let mut open = sphere_world();
+        open.camera = open.camera.with_shutter(0.0, 1.0);
+        assert_eq!(render_small(&still).buffer(), render_small(&open).buffer());
+    }
+
+    #[test]
+    fn moving_objects_only_blur_with_the_shutter_open() {
+        let moving = |shutter: (f64, f64)| {
+            let sphere = Sphere::new(Vec3::new(0.0, 0.0, 5.0), 1.5, Rgb::from_ints(255, 255, 255));
+            let scene = Scene::new()
+                .with_object(Moving::new(sphere, Vec3::new(1.0, 0.0, 0.0)))
+                .with_light(Light::ambient(1.0));
+            let camera = Camera::default().with_shutter(shutter.0, shutter.1);
+            World::new(scene, Viewport::default(), camera)
+        };
+        // Closed at the start of the frame, the sphere hasn't moved yet
+        let still = render_small(&sphere_world());
+        assert_eq!(render_small(&moving((0.0, 0.0))).buffer(), still.buffer());
+        assert_ne!(render_small(&moving((0.0, 1.0))).buffer(), still.buffer());
+    }
+}
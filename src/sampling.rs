@@ -0,0 +1,344 @@
+//! Deterministic random numbers for Monte Carlo sampling.
+//!
+//! Everything is seeded from what is being sampled rather than from shared
+//! state, so renders come out the same for any number of threads.
+
+/// A small SplitMix64 generator.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        mix(self.state)
+    }
+
+    /// Uniform in `0.0..1.0`.
+    pub fn next_f64(&mut self) -> f64 {
+        // The top 53 bits fill the mantissa exactly
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// SplitMix64's finalizer, a cheap but thorough bit mixer.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Combines `values` into one well mixed seed.
+pub fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x2545_f491_4f6c_dd1d, |h, &v| {
+        mix(h ^ v.wrapping_add(0x9e37_79b9_7f4a_7c15))
+    })
+}
+
+/// Sample `index` of `count` in the unit square, jittered inside its own cell
+/// of a grid so the samples spread evenly.
+///
+/// Samples that don't fit the largest square grid below `count` are placed
+/// anywhere.
+pub fn stratified(index: usize, count: usize, rng: &mut Rng) -> (f64, f64) {
+    let side = (count as f64).sqrt() as usize;
+    if index >= side * side {
+        return (rng.next_f64(), rng.next_f64());
+    }
+    let (column, row) = (index % side, index / side);
+    (
+        (column as f64 + rng.next_f64()) / side as f64,
+        (row as f64 + rng.next_f64()) / side as f64,
+    )
+}
+
+/// Maps the unit square onto the unit disk, keeping areas and strata intact
+/// (Shirley and Chiu's concentric mapping).
+pub fn concentric_disk(u: f64, v: f64) -> (f64, f64) {
+    let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (r, phi) = if a.abs() > b.abs() {
+        (a, std::f64::consts::FRAC_PI_4 * (b / a))
+    } else {
+        (
+            b,
+            std::f64::consts::FRAC_PI_2 - std::f64::consts::FRAC_PI_4 * (a / b),
+        )
+    };
+    (r * phi.cos(), r * phi.sin())
+}
+
+/// Where the samples of a pixel go.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SamplePattern {
+    /// Evenly spaced on a square grid, `count` is rounded up to a square
+    /// number.
+    #[default]
+    Grid,
+    /// One random point in every cell of a grid, see [`stratified`].
+    Jittered,
+    /// Anywhere, independently of each other.
+    Random,
+}
+
+impl SamplePattern {
+    /// `count` points in the unit square, more for [`SamplePattern::Grid`].
+    pub fn points(&self, count: usize, rng: &mut Rng) -> Vec<(f64, f64)> {
+        match self {
+            SamplePattern::Grid => {
+                let side = (count as f64).sqrt().ceil() as usize;
+                let cell = |i: usize| (i as f64 + 0.5) / side as f64;
+                (0..side * side)
+                    .map(|i| (cell(i % side), cell(i / side)))
+                    .collect()
+            }
+            SamplePattern::Jittered => (0..count).map(|i| stratified(i, count, rng)).collect(),
+            SamplePattern::Random => (0..count)
+                .map(|_| (rng.next_f64(), rng.next_f64()))
+                .collect(),
+        }
+    }
+}
+
+/// How samples are weighted by their distance, in pixels, from the center
+/// of the pixel they are averaged into.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Filter {
+    /// Every sample inside the pixel counts the same.
+    #[default]
+    Box,
+    /// Falls off linearly, reaching one pixel into the neighbours.
+    Tent,
+    /// A Gaussian with a standard deviation of half a pixel, cut off at 1.5
+    /// pixels.
+    Gaussian,
+    /// Mitchell and Netravali's cubic with `B = C = 1/3`, sharper than the
+    /// Gaussian. Reaches two pixels out.
+    Mitchell,
+}
+
+impl Filter {
+    /// How far from the pixel center samples are taken.
+    pub fn radius(&self) -> f64 {
+        match self {
+            Filter::Box => 0.5,
+            Filter::Tent => 1.0,
+            Filter::Gaussian => 1.5,
+            Filter::Mitchell => 2.0,
+        }
+    }
+
+    /// Weight of a sample at offset `(dx, dy)` from the pixel center, can be
+    /// negative for [`Filter::Mitchell`].
+    pub fn weight(&self, dx: f64, dy: f64) -> f64 {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    fn weight_1d(&self, x: f64) -> f64 {
+        let x = x.abs();
+        if x > self.radius() {
+            return 0.0;
+        }
+        match self {
+            Filter::Box => 1.0,
+            Filter::Tent => 1.0 - x,
+            Filter::Gaussian => {
+                // Shifted down so it reaches zero at the radius
+                let gaussian = |x: f64| (-2.0 * x * x).exp();
+                gaussian(x) - gaussian(self.radius())
+            }
+            Filter::Mitchell => {
+                let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+                let weight = if x < 1.0 {
+                    (12.0 - 9.0 * b - 6.0 * c) * x.powi(3)
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b)
+                } else {
+                    (-b - 6.0 * c) * x.powi(3)
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c)
+                };
+                weight / 6.0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTERS: [Filter; 4] = [
+        Filter::Box,
+        Filter::Tent,
+        Filter::Gaussian,
+        Filter::Mitchell,
+    ];
+    const PATTERNS: [SamplePattern; 3] = [
+        SamplePattern::Grid,
+        SamplePattern::Jittered,
+        SamplePattern::Random,
+    ];
+
+    #[test]
+    fn filters_vanish_outside_their_radius() {
+        for filter in &FILTERS {
+            let r = filter.radius();
+            for &(dx, dy) in &[
+                (r + 1e-9, 0.0),
+                (0.0, -r - 1e-9),
+                (r + 0.5, r + 0.5),
+                (-3.0, 0.0),
+            ] {
+                assert_eq!(
+                    filter.weight(dx, dy),
+                    0.0,
+                    "{:?} at ({}, {})",
+                    filter,
+                    dx,
+                    dy
+                );
+            }
+            assert!(filter.weight(0.0, 0.0) > 0.0, "{:?}", filter);
+            assert!(filter.weight(r * 0.99, 0.0).abs() <= filter.weight(0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn filters_are_symmetric_and_separable() {
+        for filter in &FILTERS {
+            for &(dx, dy) in &[(0.3, 0.1), (0.45, 0.9), (1.2, 0.7)] {
+                let w = filter.weight(dx, dy);
+                assert_eq!(filter.weight(-dx, dy), w);
+                assert_eq!(filter.weight(dx, -dy), w);
+                assert_eq!(filter.weight(dy, dx), w);
+                let separated =
+                    filter.weight(dx, 0.0) * filter.weight(0.0, dy) / filter.weight(0.0, 0.0);
+                assert!((w - separated).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn filter_weights_at_known_offsets() {
+        assert_eq!(Filter::Box.weight(0.5, -0.5), 1.0);
+        assert_eq!(Filter::Tent.weight(0.5, 0.0), 0.5);
+        assert_eq!(Filter::Tent.weight(0.5, 0.5), 0.25);
+        assert_eq!(Filter::Tent.weight(1.0, 0.0), 0.0);
+
+        // Weights are products of the horizontal and vertical ones
+        let edge = (-4.5f64).exp();
+        let center = 1.0 - edge;
+        assert!((Filter::Gaussian.weight(0.0, 0.0) - center * center).abs() < 1e-12);
+        let half = (-0.5f64).exp() - edge;
+        assert!((Filter::Gaussian.weight(0.5, 0.0) - half * center).abs() < 1e-12);
+        assert!(Filter::Gaussian.weight(1.5, 0.0).abs() < 1e-12);
+
+        let center = 8.0 / 9.0;
+        assert!((Filter::Mitchell.weight(0.0, 0.0) - center * center).abs() < 1e-12);
+        assert!((Filter::Mitchell.weight(1.0, 0.0) - center / 18.0).abs() < 1e-12);
+        assert!(Filter::Mitchell.weight(1.5, 0.0) < 0.0);
+        assert!(Filter::Mitchell.weight(2.0, 0.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn tent_and_mitchell_weights_sum_to_one_across_pixels() {
+        // A flat image stays flat when samples are spread to every pixel
+        for filter in &[Filter::Tent, Filter::Mitchell] {
+            for &(x, y) in &[(0.0, 0.0), (0.3, 0.0), (0.5, 0.25), (0.9, 0.7)] {
+                let sum: f64 = (-3..=3)
+                    .flat_map(|i| (-3..=3).map(move |j| (x + i as f64, y + j as f64)))
+                    .map(|(dx, dy)| filter.weight(dx, dy))
+                    .sum();
+                assert!(
+                    (sum - 1.0).abs() < 1e-12,
+                    "{:?} at ({}, {}): {}",
+                    filter,
+                    x,
+                    y,
+                    sum
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn patterns_fill_the_unit_square() {
+        for pattern in &PATTERNS {
+            for &count in &[1, 2, 4, 5, 9, 16, 17] {
+                let points = pattern.points(count, &mut Rng::new(1));
+                let expected = match pattern {
+                    SamplePattern::Grid => {
+                        let side = (count as f64).sqrt().ceil() as usize;
+                        side * side
+                    }
+                    _ => count,
+                };
+                assert_eq!(points.len(), expected, "{:?} of {}", pattern, count);
+                for &(x, y) in &points {
+                    assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn grid_is_centered_in_its_cells() {
+        let points = SamplePattern::Grid.points(4, &mut Rng::new(1));
+        assert_eq!(
+            points,
+            [(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)]
+        );
+        assert_eq!(
+            SamplePattern::Grid.points(1, &mut Rng::new(1)),
+            [(0.5, 0.5)]
+        );
+    }
+
+    #[test]
+    fn jittered_samples_take_one_cell_each() {
+        let mut rng = Rng::new(9);
+        let points = SamplePattern::Jittered.points(16, &mut rng);
+        let mut cells: Vec<(usize, usize)> = points
+            .iter()
+            .map(|&(x, y)| ((x * 4.0) as usize, (y * 4.0) as usize))
+            .collect();
+        cells.sort_unstable();
+        cells.dedup();
+        assert_eq!(cells.len(), 16);
+    }
+
+    #[test]
+    fn patterns_are_deterministic_per_seed() {
+        for pattern in &PATTERNS {
+            assert_eq!(
+                pattern.points(8, &mut Rng::new(5)),
+                pattern.points(8, &mut Rng::new(5))
+            );
+        }
+        assert_ne!(
+            SamplePattern::Random.points(8, &mut Rng::new(5)),
+            SamplePattern::Random.points(8, &mut Rng::new(6))
+        );
+    }
+
+    #[test]
+    fn concentric_disk_stays_inside_the_unit_disk() {
+        let mut rng = Rng::new(2);
+        for _ in 0..1000 {
+            let (x, y) = concentric_disk(rng.next_f64(), rng.next_f64());
+            assert!(x * x + y * y <= 1.0 + 1e-12);
+        }
+        assert_eq!(concentric_disk(0.5, 0.5), (0.0, 0.0));
+        let (x, y) = concentric_disk(1.0, 0.5);
+        assert!((x - 1.0).abs() < 1e-12 && y.abs() < 1e-12);
+    }
+}
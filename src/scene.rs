@@ -0,0 +1,1066 @@
+use crate::bvh::{Acceleration, Bvh};
+use crate::canvas::Rgb;
+use crate::math::{Mat3, Vec3};
+use crate::object::{Object, Ray};
+use crate::sampling::{self, Rng};
+use std::sync::OnceLock;
+
+pub use crate::object::Sphere;
+
+/// The color of lights that don't ask for one.
+const WHITE: Rgb = Rgb {
+    red: 255.0,
+    green: 255.0,
+    blue: 255.0,
+};
+
+/// How light fades with the distance `d` from its source.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Attenuation {
+    /// No falloff at all.
+    #[default]
+    None,
+    /// `1 / (constant + linear * d + quadratic * d * d)`.
+    Polynomial {
+        constant: f64,
+        linear: f64,
+        quadratic: f64,
+    },
+    /// `1 / (d * d)`, the physically based falloff.
+    InverseSquare,
+}
+
+impl Attenuation {
+    /// The fraction of light left after `distance`.
+    pub fn factor(&self, distance: f64) -> f64 {
+        match *self {
+            Attenuation::None => 1.0,
+            Attenuation::Polynomial {
+                constant,
+                linear,
+                quadratic,
+            } => 1.0 / (constant + linear * distance + quadratic * distance * distance),
+            Attenuation::InverseSquare => 1.0 / (distance * distance),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PointL {
+    pub position: Vec3,
+    pub intensity: f64,
+    pub color: Rgb,
+    pub attenuation: Attenuation,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AmbientL {
+    pub intensity: f64,
+    pub color: Rgb,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DirectionalL {
+    pub intensity: f64,
+    /// Points towards the light.
+    pub direction: Vec3,
+    pub color: Rgb,
+}
+
+/// A point light shining into a cone.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpotL {
+    pub position: Vec3,
+    /// Where the cone points, away from the light.
+    pub direction: Vec3,
+    /// Angle from `direction`, in radians, inside which the light is at full
+    /// strength.
+    pub inner_angle: f64,
+    /// Angle from `direction`, in radians, outside which there is no light.
+    /// In between it fades out smoothly.
+    pub outer_angle: f64,
+    pub intensity: f64,
+    pub color: Rgb,
+    pub attenuation: Attenuation,
+}
+
+impl SpotL {
+    /// How much of the light goes out along `to_point`, which points away
+    /// from the light.
+    pub fn cone_factor(&self, to_point: &Vec3) -> f64 {
+        let cos = to_point.normalize().dot(&self.direction.normalize());
+        let (cos_inner, cos_outer) = (self.inner_angle.cos(), self.outer_angle.cos());
+        if cos >= cos_inner {
+            return 1.0;
+        }
+        if cos <= cos_outer {
+            return 0.0;
+        }
+        // Smoothstep between the two cones
+        let x = (cos - cos_outer) / (cos_inner - cos_outer);
+        x * x * (3.0 - 2.0 * x)
+    }
+}
+
+/// The surface an [`AreaL`] shines from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LightShape {
+    /// The parallelogram `corner + a * u + b * v` for `a` and `b` in `0..=1`.
+    Rect {
+        corner: Vec3,
+        u: Vec3,
+        v: Vec3,
+    },
+    Disk {
+        center: Vec3,
+        normal: Vec3,
+        radius: f64,
+    },
+    Sphere {
+        center: Vec3,
+        radius: f64,
+    },
+}
+
+impl LightShape {
+    /// Maps `(a, b)` from the unit square onto the shape, as seen from `from`.
+    ///
+    /// Spheres are sampled on the disk they cover seen from `from`, which
+    /// casts the same shadows as their surface.
+    pub fn sample(&self, from: &Vec3, (a, b): (f64, f64)) -> Vec3 {
+        match self {
+            LightShape::Rect { corner, u, v } => *corner + *u * a + *v * b,
+            LightShape::Disk {
+                center,
+                normal,
+                radius,
+            } => disk_point(center, &normal.normalize(), *radius, (a, b)),
+            LightShape::Sphere { center, radius } => {
+                disk_point(center, &(*from - *center).normalize(), *radius, (a, b))
+            }
+        }
+    }
+}
+
+fn disk_point(center: &Vec3, normal: &Vec3, radius: f64, (a, b): (f64, f64)) -> Vec3 {
+    let (x, y) = sampling::concentric_disk(a, b);
+    let (tangent, bitangent) = normal.orthonormal_basis();
+    *center + (tangent * x + bitangent * y) * radius
+}
+
+/// A light with a size, casting soft shadows.
+///
+/// It is treated as `samples` point lights spread over its shape that share
+/// its intensity, each with its own shadow ray. More samples give smoother
+/// penumbrae at the cost of speed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AreaL {
+    pub shape: LightShape,
+    pub samples: usize,
+    pub intensity: f64,
+    pub color: Rgb,
+    pub attenuation: Attenuation,
+}
+
+/// Shadow rays per area light, unless set with [`Light::with_samples`].
+const AREA_LIGHT_SAMPLES: usize = 16;
+
+/// A light source. Its `color` is scaled by `intensity`, so a white light of
+/// intensity `1.0` shows surfaces in their own color.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Light {
+    PointL(PointL),
+    AmbientL(AmbientL),
+    DirectionalL(DirectionalL),
+    SpotL(SpotL),
+    AreaL(AreaL),
+}
+
+impl Light {
+    /// A white ambient light.
+    pub fn ambient(intensity: f64) -> Light {
+        Light::AmbientL(AmbientL {
+            intensity,
+            color: WHITE,
+        })
+    }
+
+    /// A white point light, without attenuation.
+    pub fn point(intensity: f64, position: Vec3) -> Light {
+        Light::PointL(PointL {
+            position,
+            intensity,
+            color: WHITE,
+            attenuation: Attenuation::None,
+        })
+    }
+
+    /// A white directional light.
+    pub fn directional(intensity: f64, direction: Vec3) -> Light {
+        Light::DirectionalL(DirectionalL {
+            intensity,
+            direction,
+            color: WHITE,
+        })
+    }
+
+    /// A white spotlight at `position` pointing along `direction`, without
+    /// attenuation. Angles are in radians, see [`SpotL`].
+    ///
+    /// # Panics
+    ///
+    /// If `inner_angle` is wider than `outer_angle`.
+    pub fn spot(
+        intensity: f64,
+        position: Vec3,
+        direction: Vec3,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> Light {
+        assert!(
+            inner_angle <= outer_angle,
+            "a spotlight's inner angle can't be wider than its outer angle"
+        );
+        Light::SpotL(SpotL {
+            position,
+            direction,
+            inner_angle,
+            outer_angle,
+            intensity,
+            color: WHITE,
+            attenuation: Attenuation::None,
+        })
+    }
+
+    /// A white rectangular area light, see [`LightShape::Rect`].
+    pub fn rect(intensity: f64, corner: Vec3, u: Vec3, v: Vec3) -> Light {
+        Light::area(intensity, LightShape::Rect { corner, u, v })
+    }
+
+    /// A white disk shaped area light.
+    pub fn disk(intensity: f64, center: Vec3, normal: Vec3, radius: f64) -> Light {
+        Light::area(
+            intensity,
+            LightShape::Disk {
+                center,
+                normal,
+                radius,
+            },
+        )
+    }
+
+    /// A white spherical area light.
+    pub fn sphere(intensity: f64, center: Vec3, radius: f64) -> Light {
+        Light::area(intensity, LightShape::Sphere { center, radius })
+    }
+
+    fn area(intensity: f64, shape: LightShape) -> Light {
+        Light::AreaL(AreaL {
+            shape,
+            samples: AREA_LIGHT_SAMPLES,
+            intensity,
+            color: WHITE,
+            attenuation: Attenuation::None,
+        })
+    }
+
+    pub fn with_color(mut self, color: Rgb) -> Light {
+        match &mut self {
+            Light::PointL(PointL { color: c, .. })
+            | Light::AmbientL(AmbientL { color: c, .. })
+            | Light::DirectionalL(DirectionalL { color: c, .. })
+            | Light::SpotL(SpotL { color: c, .. })
+            | Light::AreaL(AreaL { color: c, .. }) => *c = color,
+        }
+        self
+    }
+
+    /// Sets how a point, spot or area light fades with distance. Other lights
+    /// are everywhere at once and left unchanged.
+    ///
+    /// # Panics
+    ///
+    /// If a polynomial has negative coefficients, or only zeros, which would
+    /// make the light negative or infinitely bright.
+    pub fn with_attenuation(mut self, attenuation: Attenuation) -> Light {
+        if let Attenuation::Polynomial {
+            constant,
+            linear,
+            quadratic,
+        } = attenuation
+        {
+            let coefficients = [constant, linear, quadratic];
+            assert!(
+                coefficients.iter().all(|&k| k >= 0.0) && coefficients.iter().any(|&k| k > 0.0),
+                "attenuation coefficients must be at least 0, and not all 0"
+            );
+        }
+        match &mut self {
+            Light::PointL(PointL { attenuation: a, .. })
+            | Light::SpotL(SpotL { attenuation: a, .. })
+            | Light::AreaL(AreaL { attenuation: a, .. }) => *a = attenuation,
+            Light::AmbientL(_) | Light::DirectionalL(_) => {}
+        }
+        self
+    }
+
+    /// Sets the number of shadow rays of an area light, other lights only
+    /// ever need one and are left unchanged.
+    ///
+    /// # Panics
+    ///
+    /// If `samples` is zero.
+    pub fn with_samples(mut self, samples: usize) -> Light {
+        assert!(samples > 0, "an area light needs at least one sample");
+        if let Light::AreaL(area) = &mut self {
+            area.samples = samples;
+        }
+        self
+    }
+
+    /// The light's color scaled by its intensity.
+    pub fn radiance(&self) -> Rgb {
+        match self {
+            Light::PointL(PointL {
+                intensity, color, ..
+            })
+            | Light::AmbientL(AmbientL { intensity, color })
+            | Light::DirectionalL(DirectionalL {
+                intensity, color, ..
+            })
+            | Light::SpotL(SpotL {
+                intensity, color, ..
+            })
+            | Light::AreaL(AreaL {
+                intensity, color, ..
+            }) => color.multiply_by(*intensity),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Scene {
+    objects: Vec<Box<dyn Object>>,
+    pub lights: Vec<Light>,
+    /// Built on first use, and thrown away whenever an object is added.
+    bvh: OnceLock<SceneBvh>,
+}
+
+/// A BVH over the scene's bounded objects, plus the ones it can't hold.
+#[derive(Debug)]
+pub(crate) struct SceneBvh {
+    pub(crate) bvh: Bvh,
+    /// Maps the BVH's primitive indices to object indices.
+    pub(crate) bounded: Vec<usize>,
+    pub(crate) unbounded: Vec<usize>,
+}
+
+impl Scene {
+    pub fn new() -> Scene {
+        Scene::default()
+    }
+
+    pub fn with_object(mut self, object: impl Object + 'static) -> Scene {
+        self.add_object(object);
+        self
+    }
+
+    pub fn with_light(mut self, light: Light) -> Scene {
+        self.add_light(light);
+        self
+    }
+
+    pub fn add_object(&mut self, object: impl Object + 'static) {
+        self.objects.push(Box::new(object));
+        self.bvh = OnceLock::new();
+    }
+
+    pub fn objects(&self) -> &[Box<dyn Object>] {
+        &self.objects
+    }
+
+    /// Passes `acceleration` on to every object with its own acceleration
+    /// structure, see [`Object::set_acceleration`].
+    pub fn set_acceleration(&mut self, acceleration: Acceleration) {
+        for object in &mut self.objects {
+            object.set_acceleration(acceleration);
+        }
+    }
+
+    pub(crate) fn bvh(&self) -> &SceneBvh {
+        self.bvh.get_or_init(|| {
+            let (mut bounded, mut unbounded, mut bounds) = (Vec::new(), Vec::new(), Vec::new());
+            for (i, object) in self.objects.iter().enumerate() {
+                match object.bounds() {
+                    Some(aabb) => {
+                        bounded.push(i);
+                        bounds.push(aabb);
+                    }
+                    None => unbounded.push(i),
+                }
+            }
+            SceneBvh {
+                bvh: Bvh::build(&bounds),
+                bounded,
+                unbounded,
+            }
+        })
+    }
+
+    pub fn add_light(&mut self, light: Light) {
+        self.lights.push(light);
+    }
+}
+
+/// How a camera maps the canvas onto directions in the scene.
+///
+/// Every projection clips what is closer than the viewport's `d`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Projection {
+    /// A pinhole camera looking through the viewport.
+    #[default]
+    Perspective,
+    /// Parallel rays, covering the viewport's size whatever the distance.
+    Orthographic,
+    /// An equidistant fisheye, seeing `fov` radians across the largest
+    /// circle that fits the canvas. Corners outside it stay black.
+    Fisheye { fov: f64 },
+    /// A 360° by 180° panorama, longitude along x and latitude along y.
+    Equirectangular,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Camera {
+    pub position: Vec3,
+    pub rotation: Mat3,
+    pub projection: Projection,
+    /// Radius of the lens, `0.0` for a pinhole camera with everything in
+    /// focus.
+    pub aperture: f64,
+    /// Distance from the camera to where things are sharp. For perspective
+    /// and orthographic cameras it is measured along the view direction, so
+    /// the sharp region is a plane.
+    pub focus_distance: f64,
+    /// When the shutter opens, as a fraction of the frame.
+    pub shutter_open: f64,
+    /// When the shutter closes. Rays are sent at random times in between,
+    /// blurring [`Moving`](crate::object::Moving) objects.
+    pub shutter_close: f64,
+}
+
+impl Camera {
+    /// A perspective camera.
+    pub fn new(position: Vec3, rotation: Mat3) -> Camera {
+        Camera {
+            position,
+            rotation,
+            projection: Projection::Perspective,
+            aperture: 0.0,
+            focus_distance: 1.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+        }
+    }
+
+    pub fn with_projection(mut self, projection: Projection) -> Camera {
+        self.projection = projection;
+        self
+    }
+
+    /// A thin lens of radius `aperture` focused at `focus_distance`, which
+    /// blurs everything nearer or farther. Needs several samples per pixel
+    /// to look smooth.
+    pub fn with_lens(mut self, aperture: f64, focus_distance: f64) -> Camera {
+        self.aperture = aperture;
+        self.focus_distance = focus_distance;
+        self
+    }
+
+    /// Keeps the shutter open from `open` to `close`, both in `0.0..=1.0`,
+    /// for motion blur. Needs several samples per pixel to look smooth.
+    pub fn with_shutter(mut self, open: f64, close: f64) -> Camera {
+        self.shutter_open = open;
+        self.shutter_close = close;
+        self
+    }
+
+    /// The ray through point `(x, y)` of a centered `width` by `height`
+    /// canvas, or `None` if the projection doesn't cover that point.
+    ///
+    /// Rays reach the viewport's distance at `t = 1`. `rng` picks where on
+    /// the lens rays start and when they are sent, cameras without a lens
+    /// or open shutter don't use it.
+    pub fn ray(
+        &self,
+        viewport: &Viewport,
+        width: usize,
+        height: usize,
+        x: f64,
+        y: f64,
+        rng: &mut Rng,
+    ) -> Option<Ray> {
+        use std::f64::consts::PI;
+
+        let (origin, direction) = match self.projection {
+            Projection::Perspective => (Vec3::ZERO, viewport.point_at(width, height, x, y)),
+            Projection::Orthographic => {
+                let point = viewport.point_at(width, height, x, y);
+                (
+                    Vec3::new(point.x, point.y, 0.0),
+                    Vec3::new(0.0, 0.0, viewport.d),
+                )
+            }
+            Projection::Fisheye { fov } => {
+                let radius = width.min(height) as f64 / 2.0;
+                let (u, v) = (x / radius, y / radius);
+                let r = (u * u + v * v).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+                let theta = r * fov / 2.0;
+                let phi = v.atan2(u);
+                let direction = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                (Vec3::ZERO, direction * viewport.d)
+            }
+            Projection::Equirectangular => {
+                let longitude = x / width as f64 * 2.0 * PI;
+                let latitude = y / height as f64 * PI;
+                let direction = Vec3::new(
+                    latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    latitude.cos() * longitude.cos(),
+                );
+                (Vec3::ZERO, direction * viewport.d)
+            }
+        };
+        let (origin, direction) = if self.aperture > 0.0 {
+            self.through_lens(origin, direction, rng)
+        } else {
+            (origin, direction)
+        };
+        let time = if self.shutter_close > self.shutter_open {
+            self.shutter_open + (self.shutter_close - self.shutter_open) * rng.next_f64()
+        } else {
+            self.shutter_open
+        };
+        let ray = Ray::new(
+            self.position + self.rotation * origin,
+            self.rotation * direction,
+        );
+        Some(ray.with_time(time))
+    }
+
+    /// Moves the start of a pinhole ray, in camera space, to a random point
+    /// on the lens, aimed so it still meets the pinhole ray where it is in
+    /// focus.
+    fn through_lens(&self, origin: Vec3, direction: Vec3, rng: &mut Rng) -> (Vec3, Vec3) {
+        let (focus, lens_x, lens_y) = match self.projection {
+            Projection::Perspective | Projection::Orthographic => (
+                origin + direction * (self.focus_distance / direction.z),
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(0.0, 1.0, 0.0),
+            ),
+            // Wide angle rays may point sideways or backwards, so their
+            // lens faces along them and things are sharp on a sphere
+            Projection::Fisheye { .. } | Projection::Equirectangular => {
+                let forward = direction.normalize();
+                let (lens_x, lens_y) = forward.orthonormal_basis();
+                (origin + forward * self.focus_distance, lens_x, lens_y)
+            }
+        };
+
+        let (u, v) = sampling::concentric_disk(rng.next_f64(), rng.next_f64());
+        let lens = origin + (lens_x * u + lens_y * v) * self.aperture;
+        // Keep reaching the viewport's distance at `t = 1`
+        let scale = direction.length() / (focus - origin).length();
+        (lens, (focus - lens) * scale)
+    }
+
+    /// A camera at `eye` looking at `target`, turned so `up` points up on
+    /// the canvas as far as possible.
+    ///
+    /// If `up` is parallel to the view direction, as when looking straight
+    /// up or down, the positive z axis points up on the canvas instead, or
+    /// the positive y axis when looking along z. A camera whose `target` is
+    /// its `eye` looks down the positive z axis.
+    pub fn look_at(eye: Vec3, target: Vec3, up: Vec3) -> Camera {
+        let mut forward = (target - eye).normalize();
+        if forward == Vec3::ZERO {
+            forward = Vec3::new(0.0, 0.0, 1.0);
+        }
+        let mut right = up.normalize().cross(&forward);
+        if right.length() < 1e-9 {
+            let up = if forward.z.abs() > 0.5 {
+                Vec3::new(0.0, 1.0, 0.0)
+            } else {
+                Vec3::new(0.0, 0.0, 1.0)
+            };
+            right = up.cross(&forward);
+        }
+        let right = right.normalize();
+        let up = forward.cross(&right);
+        Camera::new(eye, Mat3::from_columns(right, up, forward))
+    }
+}
+
+impl Default for Camera {
+    /// At the origin, looking down the positive z axis.
+    fn default() -> Self {
+        Camera::new(Vec3::new(0.0, 0.0, 0.0), Mat3::identity())
+    }
+}
+
+/// The window into the scene, `d` units in front of the camera.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Viewport {
+    pub v_x: f64,
+    pub v_y: f64,
+    pub d: f64,
+}
+
+impl Viewport {
+    pub fn new(v_x: f64, v_y: f64, d: f64) -> Viewport {
+        Viewport { v_x, v_y, d }
+    }
+
+    /// A viewport seeing `vertical_fov` radians from top to bottom, for a
+    /// canvas `aspect` (width over height) times as wide as it is high, so
+    /// nothing is stretched.
+    pub fn from_fov(vertical_fov: f64, aspect: f64) -> Viewport {
+        let d = 1.0;
+        let v_y = 2.0 * d * (vertical_fov / 2.0).tan();
+        Viewport::new(v_y * aspect, v_y, d)
+    }
+
+    /// Maps a centered canvas pixel onto the viewport, in camera space.
+    pub fn point_from_canvas(&self, width: usize, height: usize, x: i32, y: i32) -> Vec3 {
+        self.point_at(width, height, x as f64, y as f64)
+    }
+
+    /// Like [`Viewport::point_from_canvas`], for any point on the canvas
+    /// rather than just pixel centers.
+    pub fn point_at(&self, width: usize, height: usize, x: f64, y: f64) -> Vec3 {
+        Vec3 {
+            x: (x / width as f64) * self.v_x,
+            y: (y / height as f64) * self.v_y,
+            z: self.d,
+        }
+    }
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Viewport::new(1.0, 1.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+    const EPS: f64 = 1e-9;
+
+    fn assert_vec_eq(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < EPS, "{:?} != {:?}", a, b);
+    }
+
+    fn assert_rotation(m: &Mat3) {
+        assert!((m.determinant() - 1.0).abs() < EPS, "{:?}", m);
+        let product = m.transpose() * *m;
+        for (x, y) in product
+            .fields
+            .iter()
+            .flatten()
+            .zip(Mat3::identity().fields.iter().flatten())
+        {
+            assert!((x - y).abs() < EPS, "{:?}", m);
+        }
+    }
+
+    const UP: Vec3 = Vec3::new(0.0, 1.0, 0.0);
+
+    #[test]
+    fn look_at_down_positive_z_is_the_identity() {
+        let camera = Camera::look_at(Vec3::new(1.0, 2.0, 3.0), Vec3::new(1.0, 2.0, 10.0), UP);
+        assert_eq!(camera.position, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(camera.rotation, Mat3::identity());
+    }
+
+    #[test]
+    fn look_at_points_the_view_direction_at_the_target() {
+        let eye = Vec3::new(0.0, 1.5, -4.0);
+        let target = Vec3::new(2.0, 0.5, 2.0);
+        let camera = Camera::look_at(eye, target, UP);
+        assert_rotation(&camera.rotation);
+        assert_vec_eq(camera.rotation.column(2), (target - eye).normalize());
+        // No roll, the canvas' x axis stays level
+        assert!(camera.rotation.column(0).y.abs() < EPS);
+        assert!(camera.rotation.column(1).y > 0.0);
+    }
+
+    #[test]
+    fn look_at_straight_up_or_down_keeps_z_up_on_the_canvas() {
+        for &target in &[Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -5.0, 0.0)] {
+            let camera = Camera::look_at(Vec3::ZERO, target, UP);
+            assert_rotation(&camera.rotation);
+            assert_vec_eq(camera.rotation.column(2), target.normalize());
+            assert_vec_eq(camera.rotation.column(1), Vec3::new(0.0, 0.0, 1.0));
+        }
+
+        // The same along z, with a z up vector
+        let z = Vec3::new(0.0, 0.0, 1.0);
+        let camera = Camera::look_at(Vec3::ZERO, Vec3::new(0.0, 0.0, -2.0), z);
+        assert_rotation(&camera.rotation);
+        assert_vec_eq(camera.rotation.column(1), UP);
+    }
+
+    #[test]
+    fn look_at_its_own_position_looks_down_positive_z() {
+        let eye = Vec3::new(1.0, 2.0, 3.0);
+        let camera = Camera::look_at(eye, eye, UP);
+        assert_eq!(camera.rotation, Mat3::identity());
+    }
+
+    #[test]
+    fn viewport_from_fov() {
+        let viewport = Viewport::from_fov(90f64.to_radians(), 1.0);
+        assert!((viewport.v_x - 2.0).abs() < EPS && (viewport.v_y - 2.0).abs() < EPS);
+        assert_eq!(viewport.d, 1.0);
+
+        let wide = Viewport::from_fov(90f64.to_radians(), 16.0 / 9.0);
+        assert!((wide.v_x - 32.0 / 9.0).abs() < EPS && (wide.v_y - 2.0).abs() < EPS);
+
+        let narrow = Viewport::from_fov(60f64.to_radians(), 1.0);
+        assert!((narrow.v_y - 2.0 / 3f64.sqrt()).abs() < EPS);
+    }
+
+    #[test]
+    fn viewport_edges_match_the_canvas_edges() {
+        let viewport = Viewport::new(4.0, 2.0, 1.5);
+        assert_vec_eq(
+            viewport.point_at(800, 400, 400.0, -200.0),
+            Vec3::new(2.0, -1.0, 1.5),
+        );
+        assert_vec_eq(
+            viewport.point_from_canvas(800, 400, 0, 0),
+            Vec3::new(0.0, 0.0, 1.5),
+        );
+    }
+
+    fn ray(camera: &Camera, x: f64, y: f64) -> Option<Ray> {
+        let viewport = Viewport::new(2.0, 1.0, 1.0);
+        camera.ray(&viewport, 200, 100, x, y, &mut Rng::new(0))
+    }
+
+    #[test]
+    fn perspective_rays_start_at_the_camera() {
+        let camera = Camera::new(Vec3::new(1.0, 2.0, 3.0), Mat3::rotation_y(FRAC_PI_2));
+        let center = ray(&camera, 0.0, 0.0).unwrap();
+        assert_vec_eq(center.origin, Vec3::new(1.0, 2.0, 3.0));
+        // The rotation turns +z into +x
+        assert_vec_eq(center.direction, Vec3::new(1.0, 0.0, 0.0));
+
+        let corner = ray(&Camera::default(), 100.0, 50.0).unwrap();
+        assert_vec_eq(corner.direction, Vec3::new(1.0, 0.5, 1.0));
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let camera = Camera::default().with_projection(Projection::Orthographic);
+        let center = ray(&camera, 0.0, 0.0).unwrap();
+        let corner = ray(&camera, -100.0, 50.0).unwrap();
+        assert_vec_eq(center.origin, Vec3::ZERO);
+        assert_vec_eq(corner.origin, Vec3::new(-1.0, 0.5, 0.0));
+        assert_vec_eq(center.direction, Vec3::new(0.0, 0.0, 1.0));
+        assert_vec_eq(corner.direction, center.direction);
+    }
+
+    #[test]
+    fn fisheye_maps_the_radius_to_the_angle() {
+        let camera = Camera::default().with_projection(Projection::Fisheye { fov: PI });
+        // The largest circle on a 200 by 100 canvas has a radius of 50
+        assert_vec_eq(
+            ray(&camera, 0.0, 0.0).unwrap().direction,
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        assert_vec_eq(
+            ray(&camera, 50.0, 0.0).unwrap().direction,
+            Vec3::new(1.0, 0.0, 0.0),
+        );
+        assert_vec_eq(
+            ray(&camera, 0.0, -50.0).unwrap().direction,
+            Vec3::new(0.0, -1.0, 0.0),
+        );
+        let halfway = ray(&camera, 25.0, 0.0).unwrap().direction;
+        assert_vec_eq(halfway, Vec3::new(FRAC_PI_4.sin(), 0.0, FRAC_PI_4.cos()));
+        assert!(ray(&camera, 51.0, 0.0).is_none());
+        assert!(ray(&camera, 40.0, 40.0).is_none());
+    }
+
+    #[test]
+    fn equirectangular_covers_every_direction() {
+        let camera = Camera::default().with_projection(Projection::Equirectangular);
+        let direction = |x, y| ray(&camera, x, y).unwrap().direction;
+        assert_vec_eq(direction(0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        assert_vec_eq(direction(50.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_vec_eq(direction(-100.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_vec_eq(direction(0.0, 50.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_vec_eq(direction(0.0, -50.0), Vec3::new(0.0, -1.0, 0.0));
+    }
+
+    const PROJECTIONS: [Projection; 4] = [
+        Projection::Perspective,
+        Projection::Orthographic,
+        Projection::Fisheye { fov: PI },
+        Projection::Equirectangular,
+    ];
+
+    #[test]
+    fn lens_without_aperture_is_a_pinhole() {
+        let rotation = Mat3::from_euler(0.1, 0.5, -0.2);
+        for &projection in &PROJECTIONS {
+            let pinhole =
+                Camera::new(Vec3::new(1.0, 2.0, 3.0), rotation).with_projection(projection);
+            let lens = pinhole.clone().with_lens(0.0, 7.0);
+            for &(x, y) in &[(0.0, 0.0), (30.0, -20.0), (-45.0, 10.0)] {
+                assert_eq!(ray(&lens, x, y), ray(&pinhole, x, y), "{:?}", projection);
+            }
+        }
+    }
+
+    #[test]
+    fn lens_rays_meet_the_pinhole_ray_in_focus() {
+        let rotation = Mat3::from_euler(0.1, 0.5, -0.2);
+        let viewport = Viewport::new(2.0, 1.0, 1.0);
+        for &projection in &PROJECTIONS {
+            let pinhole =
+                Camera::new(Vec3::new(1.0, 2.0, 3.0), rotation).with_projection(projection);
+            let lens = pinhole.clone().with_lens(0.5, 4.0);
+            let sharp = pinhole
+                .ray(&viewport, 200, 100, 30.0, -20.0, &mut Rng::new(0))
+                .unwrap();
+            // Where the pinhole ray is in focus
+            let focus = match projection {
+                Projection::Perspective | Projection::Orthographic => sharp.at(4.0),
+                _ => sharp.at(4.0 / sharp.direction.length()),
+            };
+
+            let mut rng = Rng::new(1);
+            let mut origins = Vec::new();
+            for _ in 0..8 {
+                let ray = lens
+                    .ray(&viewport, 200, 100, 30.0, -20.0, &mut rng)
+                    .unwrap();
+                assert!((ray.origin - sharp.origin).length() <= 0.5 + EPS);
+                // Closest approach of the ray to the focus point
+                let t = (focus - ray.origin).dot(&ray.direction) / ray.direction.length_squared();
+                assert_vec_eq(ray.at(t), focus);
+                origins.push(ray.origin);
+            }
+            assert!(origins.iter().any(|o| (*o - sharp.origin).length() > 0.01));
+        }
+    }
+
+    #[test]
+    fn attenuation_falls_off_with_distance() {
+        assert_eq!(Attenuation::None.factor(100.0), 1.0);
+        assert_eq!(Attenuation::InverseSquare.factor(2.0), 0.25);
+        assert_eq!(Attenuation::InverseSquare.factor(0.5), 4.0);
+        let polynomial = Attenuation::Polynomial {
+            constant: 1.0,
+            linear: 0.5,
+            quadratic: 0.25,
+        };
+        assert_eq!(polynomial.factor(0.0), 1.0);
+        assert!((polynomial.factor(2.0) - 1.0 / 3.0).abs() < EPS);
+        assert!((polynomial.factor(4.0) - 1.0 / 7.0).abs() < EPS);
+    }
+
+    #[test]
+    fn attenuation_is_only_set_on_lights_with_a_position() {
+        let falloff = Attenuation::InverseSquare;
+        let point = Light::point(1.0, Vec3::ZERO).with_attenuation(falloff);
+        assert!(
+            matches!(point, Light::PointL(PointL { attenuation, .. }) if attenuation == falloff)
+        );
+        let ambient = Light::ambient(1.0).with_attenuation(falloff);
+        assert_eq!(ambient, Light::ambient(1.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "not all 0")]
+    fn attenuation_without_coefficients_panics() {
+        Light::point(1.0, Vec3::ZERO).with_attenuation(Attenuation::Polynomial {
+            constant: 0.0,
+            linear: 0.0,
+            quadratic: 0.0,
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 0")]
+    fn attenuation_with_negative_coefficients_panics() {
+        Light::point(1.0, Vec3::ZERO).with_attenuation(Attenuation::Polynomial {
+            constant: 1.0,
+            linear: -1.0,
+            quadratic: 0.0,
+        });
+    }
+
+    /// Pointing down `z`, full inside 30 degrees and dark beyond 60.
+    fn spot() -> SpotL {
+        match Light::spot(
+            1.0,
+            Vec3::ZERO,
+            Vec3::new(0.0, 0.0, 2.0),
+            PI / 6.0,
+            PI / 3.0,
+        ) {
+            Light::SpotL(spot) => spot,
+            _ => unreachable!(),
+        }
+    }
+
+    /// A direction `angle` radians away from the spot's axis.
+    fn off_axis(angle: f64) -> Vec3 {
+        Vec3::new(angle.sin(), 0.0, angle.cos()) * 3.0
+    }
+
+    #[test]
+    fn spotlights_are_full_inside_and_dark_outside_the_cone() {
+        let spot = spot();
+        for &angle in &[0.0, 0.1, PI / 6.0 - 1e-6] {
+            assert_eq!(spot.cone_factor(&off_axis(angle)), 1.0, "{}", angle);
+        }
+        for &angle in &[PI / 3.0 + 1e-6, FRAC_PI_2, PI] {
+            assert_eq!(spot.cone_factor(&off_axis(angle)), 0.0, "{}", angle);
+        }
+    }
+
+    #[test]
+    fn spotlights_fade_smoothly_between_the_cones() {
+        let spot = spot();
+        // Halfway between the cones' cosines the smoothstep is at one half
+        let cos = (f64::cos(PI / 6.0) + f64::cos(PI / 3.0)) / 2.0;
+        assert!((spot.cone_factor(&off_axis(cos.acos())) - 0.5).abs() < EPS);
+
+        let mut last = 1.0;
+        for step in 1..100 {
+            let angle = PI / 6.0 + PI / 6.0 * step as f64 / 100.0;
+            let factor = spot.cone_factor(&off_axis(angle));
+            assert!(factor > 0.0 && factor < 1.0, "{}", angle);
+            assert!(factor < last, "{}", angle);
+            last = factor;
+        }
+        // No jumps at the edges of the band
+        assert!(spot.cone_factor(&off_axis(PI / 6.0 + 1e-4)) > 0.999);
+        assert!(spot.cone_factor(&off_axis(PI / 3.0 - 1e-4)) < 0.001);
+    }
+
+    #[test]
+    fn spotlights_may_have_a_hard_edge() {
+        let spot = Light::spot(1.0, Vec3::ZERO, Vec3::new(0.0, 0.0, 1.0), 0.5, 0.5);
+        let spot = match spot {
+            Light::SpotL(spot) => spot,
+            _ => unreachable!(),
+        };
+        assert_eq!(spot.cone_factor(&off_axis(0.49)), 1.0);
+        assert_eq!(spot.cone_factor(&off_axis(0.51)), 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "inner angle can't be wider")]
+    fn spotlights_with_the_cones_swapped_panic() {
+        Light::spot(1.0, Vec3::ZERO, Vec3::new(0.0, 0.0, 1.0), 0.6, 0.5);
+    }
+
+    /// Every `(a, b)` on a 9 by 9 grid over the unit square, edges included.
+    fn unit_square() -> impl Iterator<Item = (f64, f64)> {
+        (0..81).map(|i| ((i % 9) as f64 / 8.0, (i / 9) as f64 / 8.0))
+    }
+
+    #[test]
+    fn rect_samples_cover_the_parallelogram() {
+        let (corner, u, v) = (
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(2.0, 0.0, 1.0),
+            Vec3::new(0.0, 3.0, 0.0),
+        );
+        let rect = LightShape::Rect { corner, u, v };
+        let from = Vec3::new(0.0, 0.0, -10.0);
+        assert_vec_eq(rect.sample(&from, (0.0, 0.0)), corner);
+        assert_vec_eq(rect.sample(&from, (1.0, 1.0)), corner + u + v);
+        assert_vec_eq(rect.sample(&from, (0.5, 0.25)), corner + u * 0.5 + v * 0.25);
+
+        let normal = u.cross(&v).normalize();
+        for (a, b) in unit_square() {
+            let offset = rect.sample(&from, (a, b)) - corner;
+            assert!(offset.dot(&normal).abs() < EPS);
+            // Coordinates along the edges, which are orthogonal here
+            let along_u = offset.dot(&u) / u.dot(&u);
+            let along_v = offset.dot(&v) / v.dot(&v);
+            assert!((along_u - a).abs() < EPS && (along_v - b).abs() < EPS);
+        }
+    }
+
+    #[test]
+    fn disk_samples_stay_on_the_disk() {
+        let (center, normal, radius) = (Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -2.0, 0.0), 1.5);
+        let disk = LightShape::Disk {
+            center,
+            normal,
+            radius,
+        };
+        let mut farthest: f64 = 0.0;
+        for (a, b) in unit_square() {
+            let offset = disk.sample(&Vec3::ZERO, (a, b)) - center;
+            assert!(offset.dot(&normal).abs() < EPS);
+            assert!(offset.length() <= radius + EPS);
+            farthest = farthest.max(offset.length());
+        }
+        // The square's edges map onto the rim, its middle onto the center
+        assert!((farthest - radius).abs() < EPS);
+        assert_vec_eq(disk.sample(&Vec3::ZERO, (0.5, 0.5)), center);
+    }
+
+    #[test]
+    fn sphere_samples_lie_on_the_disk_facing_the_point() {
+        let (center, radius) = (Vec3::new(1.0, 1.0, 1.0), 0.5);
+        let sphere = LightShape::Sphere { center, radius };
+        for &from in &[Vec3::ZERO, Vec3::new(5.0, -3.0, 2.0)] {
+            let towards = from - center;
+            for (a, b) in unit_square() {
+                let offset = sphere.sample(&from, (a, b)) - center;
+                assert!(offset.dot(&towards).abs() < EPS);
+                assert!(offset.length() <= radius + EPS);
+            }
+        }
+    }
+
+    #[test]
+    fn area_lights_keep_their_sample_count() {
+        let samples = |light: &Light| match light {
+            Light::AreaL(area) => area.samples,
+            _ => unreachable!(),
+        };
+        let light = Light::sphere(1.0, Vec3::ZERO, 1.0);
+        assert_eq!(samples(&light), AREA_LIGHT_SAMPLES);
+        assert_eq!(samples(&light.with_samples(3)), 3);
+        // Other lights ignore it
+        assert_eq!(
+            Light::point(1.0, Vec3::ZERO).with_samples(3),
+            Light::point(1.0, Vec3::ZERO)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one sample")]
+    fn area_lights_without_samples_panic() {
+        Light::sphere(1.0, Vec3::ZERO, 1.0).with_samples(0);
+    }
+}
@@ -0,0 +1,1510 @@
+//! Scene description files.
+//!
+//! Scenes are written in a small subset of TOML: `key = value` pairs grouped
+//! under `[table]` headers, where values are numbers, `"strings"`, booleans
+//! or `[arrays]` of values, and `#` starts a comment.
+//!
+//! ```toml
+//! [camera]
+//! position = [0, 2, -5]
+//! look_at = [0, 0, 3]
+//!
+//! [viewport]
+//! fov = 53.13
+//!
+//! [materials.red]
+//! color = [255, 0, 0]
+//! specular = 500
+//! reflective = 0.2
+//!
+//! [[object]]
+//! type = "sphere"
+//! center = [0, -1, 3]
+//! radius = 1
+//! material = "red"
+//!
+//! [[light]]
+//! type = "point"
+//! intensity = 0.6
+//! position = [2, 1, 0]
+//! ```
+//!
+//! * `[camera]` has a `position` and either a `rotation`, given as three
+//!   rows, or a point to `look_at` with an optional `up`. It may also set the
+//!   `projection` (`"perspective"`, `"orthographic"`, `"fisheye"` with a
+//!   `fov` in degrees, or `"equirectangular"`), a lens with `aperture` and
+//!   `focus_distance`, and a `shutter = [open, close]` within `0` to `1`
+//! * `[viewport]` has a `width`, `height` and `distance`, or a vertical `fov`
+//!   in degrees with an optional `aspect`
+//! * `[materials.NAME]` has a `color` from `0` to `255`, and optionally a
+//!   `specular` exponent (`-1` for matte), `reflective` and `transparency`
+//!   from `0` to `1`, and a `refractive_index`
+//! * each `[[object]]` has a `type` of `"sphere"`, `"plane"`, `"quad"`,
+//!   `"disk"`, `"triangle"` or `"mesh"` and the matching fields. Its material
+//!   is a named `material`, material keys, or both to override some keys. A
+//!   `translation` makes it move during the frame. Meshes are loaded from an
+//!   OBJ `file` relative to the scene file and keep its materials unless the
+//!   object gives one
+//! * each `[[light]]` has a `type` of `"ambient"`, `"point"`,
+//!   `"directional"`, `"spot"`, `"rect"`, `"disk"` or `"sphere"`, an
+//!   `intensity` and an optional `color`. Point, spot and area lights take an
+//!   `attenuation` of `"none"`, `"inverse_square"` or
+//!   `[constant, linear, quadratic]`, spotlight angles are in degrees with
+//!   the `inner_angle` no wider than the `outer_angle`, and area lights take
+//!   a number of `samples`
+//!
+//! Unknown tables and keys are errors rather than ignored, so typos don't go
+//! unnoticed. So are numbers that aren't finite, sizes that aren't positive,
+//! zero normals and directions, and shapes without area.
+
+use crate::canvas::Rgb;
+use crate::math::{Mat3, Vec3};
+use crate::mesh::Triangle;
+use crate::obj::{self, ObjError};
+use crate::object::{Material, Moving, Object, Sphere, WithMaterial};
+use crate::planar::{Disk, Plane, Quad};
+use crate::raytracer::World;
+use crate::scene::{Attenuation, Camera, Light, Projection, Scene, Viewport};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::Chars;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parse {
+        /// Unknown when parsing from a string.
+        path: Option<PathBuf>,
+        line: usize,
+        message: String,
+    },
+    /// A mesh's OBJ file couldn't be loaded.
+    Obj(ObjError),
+}
+
+impl SceneError {
+    fn parse(line: usize, message: impl Into<String>) -> SceneError {
+        SceneError::Parse {
+            path: None,
+            line,
+            message: message.into(),
+        }
+    }
+
+    fn in_file(self, file: &Path) -> SceneError {
+        match self {
+            SceneError::Parse {
+                path: None,
+                line,
+                message,
+            } => SceneError::Parse {
+                path: Some(file.to_path_buf()),
+                line,
+                message,
+            },
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            SceneError::Parse {
+                path,
+                line,
+                message,
+            } => {
+                if let Some(path) = path {
+                    write!(f, "{}:", path.display())?;
+                }
+                write!(f, "{}: {}", line, message)
+            }
+            SceneError::Obj(error) => error.fmt(f),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneError::Io { error, .. } => Some(error),
+            SceneError::Parse { .. } => None,
+            SceneError::Obj(error) => Some(error),
+        }
+    }
+}
+
+impl From<ObjError> for SceneError {
+    fn from(error: ObjError) -> SceneError {
+        SceneError::Obj(error)
+    }
+}
+
+/// A loaded scene with what the file left to the renderer.
+pub struct Description {
+    pub world: World,
+    /// Whether `[viewport]` gives a `width` or an `aspect`. Otherwise the
+    /// viewport is square and callers may widen it to match the canvas.
+    pub sets_width: bool,
+}
+
+/// Loads a scene file, see the [module documentation](self) for the format.
+pub fn load(path: impl AsRef<Path>) -> Result<World, SceneError> {
+    load_description(path).map(|description| description.world)
+}
+
+/// Builds the world described by `source`. Mesh files are looked up relative
+/// to `directory`.
+pub fn parse(source: &str, directory: &Path) -> Result<World, SceneError> {
+    parse_description(source, directory).map(|description| description.world)
+}
+
+/// Like [`load`], but also tells what the file left unset.
+pub fn load_description(path: impl AsRef<Path>) -> Result<Description, SceneError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|error| SceneError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    parse_description(&source, directory).map_err(|e| e.in_file(path))
+}
+
+/// Like [`parse`], but also tells what the source left unset.
+pub fn parse_description(source: &str, directory: &Path) -> Result<Description, SceneError> {
+    let tables = Parser::new(source).document()?;
+
+    let mut camera = None;
+    let mut viewport = None;
+    let mut materials = HashMap::new();
+    let mut objects = Vec::new();
+    let mut lights = Vec::new();
+    for table in &tables {
+        match (table.name.as_str(), table.array) {
+            ("", _) => {
+                if let Some(entry) = table.entries.first() {
+                    return Err(SceneError::parse(
+                        entry.line,
+                        format!(
+                            "`{}` must be inside a table such as [camera] or [[object]]",
+                            entry.key
+                        ),
+                    ));
+                }
+            }
+            ("camera", false) => camera = Some(build_camera(Fields::new(table, "camera"))?),
+            ("viewport", false) => viewport = Some(build_viewport(Fields::new(table, "viewport"))?),
+            ("object", true) => objects.push(table),
+            ("light", true) => lights.push(build_light(Fields::new(table, "light"))?),
+            (name, false) if name.starts_with("materials.") => {
+                let name = &name["materials.".len()..];
+                let mut fields = Fields::new(table, &format!("material `{}`", name));
+                let material = build_material(&mut fields, None)?;
+                fields.finish()?;
+                materials.insert(name.to_string(), material);
+            }
+            (name @ "object", false) | (name @ "light", false) => {
+                return Err(SceneError::parse(
+                    table.line,
+                    format!("every {} is its own [[{}]] table", name, name),
+                ))
+            }
+            (name @ "camera", true) | (name @ "viewport", true) => {
+                return Err(SceneError::parse(
+                    table.line,
+                    format!("a scene has a single [{}] table", name),
+                ))
+            }
+            (name, _) => {
+                return Err(SceneError::parse(
+                    table.line,
+                    format!(
+                        "unknown table `{}`, expected camera, viewport, materials.NAME, \
+                         object or light",
+                        name
+                    ),
+                ))
+            }
+        }
+    }
+
+    // Objects are built last, so they can use materials defined after them
+    let mut scene = Scene::new();
+    for table in objects {
+        add_object(
+            &mut scene,
+            Fields::new(table, "object"),
+            &materials,
+            directory,
+        )?;
+    }
+    for light in lights {
+        scene.add_light(light);
+    }
+
+    let (viewport, sets_width) = viewport.unwrap_or_default();
+    Ok(Description {
+        world: World::new(scene, viewport, camera.unwrap_or_default()),
+        sets_width,
+    })
+}
+
+fn build_camera(mut fields: Fields) -> Result<Camera, SceneError> {
+    let position = fields.vec3("position")?.unwrap_or(Vec3::ZERO);
+    let rotation = fields.rows("rotation")?;
+    let target = fields.vec3("look_at")?;
+    let up = fields.direction("up")?;
+    let mut camera = match (rotation, target) {
+        (Some(_), Some(_)) => {
+            return Err(fields.error("sets both `rotation` and `look_at`, only one is allowed"))
+        }
+        (Some(rotation), None) => {
+            if up.is_some() {
+                return Err(fields.error("sets `up`, which only applies with `look_at`"));
+            }
+            Camera::new(position, rotation)
+        }
+        (None, Some(target)) if target == position => {
+            return Err(fields.error("looks at its own `position`, `look_at` must be elsewhere"))
+        }
+        (None, Some(target)) => {
+            let up = up.unwrap_or(Vec3::new(0.0, 1.0, 0.0));
+            Camera::look_at(position, target, up)
+        }
+        (None, None) => Camera {
+            position,
+            ..Camera::default()
+        },
+    };
+
+    let fov = fields.number_where("fov", |n| n > 0.0 && n <= 360.0, "an angle from 0 to 360")?;
+    camera.projection = match fields.string("projection")?.as_deref() {
+        None | Some("perspective") if fov.is_none() => Projection::Perspective,
+        Some("orthographic") if fov.is_none() => Projection::Orthographic,
+        Some("equirectangular") if fov.is_none() => Projection::Equirectangular,
+        Some("fisheye") => Projection::Fisheye {
+            fov: fov.unwrap_or(180.0).to_radians(),
+        },
+        None | Some("perspective") | Some("orthographic") | Some("equirectangular") => {
+            return Err(fields
+                .error("sets a `fov`, which only applies to fisheye cameras, use the viewport's"))
+        }
+        Some(other) => {
+            return Err(fields.error(format!(
+                "has an unknown projection `{}`, expected perspective, orthographic, \
+                 fisheye or equirectangular",
+                other
+            )))
+        }
+    };
+
+    let aperture = fields
+        .number_where("aperture", |n| n >= 0.0, "a number of at least 0")?
+        .unwrap_or(0.0);
+    let focus_distance = fields.positive("focus_distance")?.unwrap_or(1.0);
+    camera = camera.with_lens(aperture, focus_distance);
+    if let Some(entry) = fields.get("shutter") {
+        match entry.numbers()? {
+            [open, close] if 0.0 <= open && open <= close && close <= 1.0 => {
+                camera = camera.with_shutter(open, close)
+            }
+            [open, close] => {
+                return Err(entry.error(format!(
+                    "`shutter` expects times from 0 to 1, opening before it closes, \
+                     found [{}, {}]",
+                    open, close
+                )))
+            }
+        }
+    }
+
+    fields.finish()?;
+    Ok(camera)
+}
+
+/// The viewport and whether it sets its width, directly or through `aspect`.
+fn build_viewport(mut fields: Fields) -> Result<(Viewport, bool), SceneError> {
+    let width = fields.positive("width")?;
+    let height = fields.positive("height")?;
+    let distance = fields.positive("distance")?;
+    let fov = fields.number_where(
+        "fov",
+        |n| n > 0.0 && n < 180.0,
+        "an angle between 0 and 180",
+    )?;
+    let aspect = fields.positive("aspect")?;
+    let viewport = match fov {
+        Some(_) if width.is_some() || height.is_some() || distance.is_some() => {
+            return Err(fields.error("sets both a `fov` and a size, only one is allowed"))
+        }
+        Some(fov) => Viewport::from_fov(fov.to_radians(), aspect.unwrap_or(1.0)),
+        None if aspect.is_some() => {
+            return Err(fields.error("sets `aspect`, which only applies with `fov`"))
+        }
+        None => Viewport::new(
+            width.unwrap_or(1.0),
+            height.unwrap_or(1.0),
+            distance.unwrap_or(1.0),
+        ),
+    };
+    fields.finish()?;
+    Ok((viewport, width.is_some() || aspect.is_some()))
+}
+
+/// The material keys of `fields` applied over `base`, or `None` if there are
+/// neither keys nor a base.
+fn material_keys(
+    fields: &mut Fields,
+    base: Option<Material>,
+) -> Result<Option<Material>, SceneError> {
+    let color = fields.color("color")?;
+    let mut material = match (base, color) {
+        (Some(base), Some(color)) => Material { color, ..base },
+        (Some(base), None) => base,
+        (None, Some(color)) => Material::new(color),
+        (None, None) => {
+            for key in &["specular", "reflective", "transparency", "refractive_index"] {
+                if fields.has(key) {
+                    return Err(fields.error("needs a `color` for its material"));
+                }
+            }
+            return Ok(None);
+        }
+    };
+    if let Some(specular) = fields.specular("specular")? {
+        material.specular = specular;
+    }
+    if let Some(reflective) = fields.fraction("reflective")? {
+        material.reflective = reflective;
+    }
+    if let Some(transparency) = fields.fraction("transparency")? {
+        material.transparency = transparency;
+    }
+    if let Some(refractive_index) = fields.positive("refractive_index")? {
+        material.refractive_index = refractive_index;
+    }
+    Ok(Some(material))
+}
+
+fn build_material(
+    fields: &mut Fields,
+    materials: Option<&HashMap<String, Material>>,
+) -> Result<Material, SceneError> {
+    let base = match materials {
+        Some(materials) => match fields.string("material")? {
+            Some(name) => match materials.get(&name) {
+                Some(material) => Some(material.clone()),
+                None => return Err(fields.error(format!("uses an undefined material `{}`", name))),
+            },
+            None => None,
+        },
+        None => None,
+    };
+    match material_keys(fields, base)? {
+        Some(material) => Ok(material),
+        None => Err(fields.error("needs a `color` or a `material`")),
+    }
+}
+
+fn add_moving(scene: &mut Scene, object: impl Object + 'static, translation: Option<Vec3>) {
+    match translation {
+        Some(translation) => scene.add_object(Moving::new(object, translation)),
+        None => scene.add_object(object),
+    }
+}
+
+fn add_object(
+    scene: &mut Scene,
+    mut fields: Fields,
+    materials: &HashMap<String, Material>,
+    directory: &Path,
+) -> Result<(), SceneError> {
+    let kind = fields.required_string("type")?;
+    if !["sphere", "plane", "quad", "disk", "triangle", "mesh"].contains(&kind.as_str()) {
+        return Err(fields.error(format!(
+            "has an unknown type, expected sphere, plane, quad, disk, triangle or mesh, \
+             found `{}`",
+            kind
+        )));
+    }
+    fields.what = kind.clone();
+    let translation = fields.vec3("translation")?;
+
+    if kind == "mesh" {
+        let file = fields.required_string("file")?;
+        let smooth = fields.boolean("smooth")?.unwrap_or(false);
+        let material = if fields.has("material") {
+            Some(build_material(&mut fields, Some(materials))?)
+        } else {
+            material_keys(&mut fields, None)?
+        };
+        fields.finish()?;
+        for mut mesh in obj::load(directory.join(file))? {
+            if smooth {
+                mesh = mesh.with_smooth_normals();
+            }
+            if let Some(material) = &material {
+                mesh.material = material.clone();
+            }
+            add_moving(scene, mesh, translation);
+        }
+        return Ok(());
+    }
+
+    let material = build_material(&mut fields, Some(materials))?;
+    let color = material.color;
+    match kind.as_str() {
+        "sphere" => {
+            let center = fields.required(Fields::vec3, "center")?;
+            let radius = fields.required(Fields::positive, "radius")?;
+            let sphere = Sphere::new(center, radius, color).with_material(material);
+            fields.finish()?;
+            add_moving(scene, sphere, translation);
+        }
+        "plane" => {
+            let point = fields.required(Fields::vec3, "point")?;
+            let normal = fields.required(Fields::direction, "normal")?;
+            let plane = Plane::new(point, normal, color).with_material(material);
+            fields.finish()?;
+            add_moving(scene, plane, translation);
+        }
+        "quad" => {
+            let corner = fields.required(Fields::vec3, "corner")?;
+            let u = fields.required(Fields::vec3, "u")?;
+            let v = fields.required(Fields::vec3, "v")?;
+            if u.cross(&v) == Vec3::ZERO {
+                return Err(fields.error("has parallel `u` and `v`, so it has no area"));
+            }
+            let mut quad = Quad::new(corner, u, v, color).with_material(material);
+            if fields.boolean("one_sided")?.unwrap_or(false) {
+                quad = quad.one_sided();
+            }
+            fields.finish()?;
+            add_moving(scene, quad, translation);
+        }
+        "disk" => {
+            let center = fields.required(Fields::vec3, "center")?;
+            let normal = fields.required(Fields::direction, "normal")?;
+            let radius = fields.required(Fields::positive, "radius")?;
+            let mut disk = Disk::new(center, normal, radius, color).with_material(material);
+            if fields.boolean("one_sided")?.unwrap_or(false) {
+                disk = disk.one_sided();
+            }
+            fields.finish()?;
+            add_moving(scene, disk, translation);
+        }
+        "triangle" => {
+            let [p0, p1, p2] = fields.required(Fields::points, "vertices")?;
+            if (p1 - p0).cross(&(p2 - p0)) == Vec3::ZERO {
+                return Err(fields.error("has `vertices` on a line, so it has no area"));
+            }
+            let mut triangle = Triangle::new(p0, p1, p2, color).with_material(material);
+            if let Some([n0, n1, n2]) = fields.points("normals")? {
+                triangle = triangle.with_normals(n0, n1, n2);
+            }
+            fields.finish()?;
+            add_moving(scene, triangle, translation);
+        }
+        _ => unreachable!("object types are checked above"),
+    }
+    Ok(())
+}
+
+fn build_light(mut fields: Fields) -> Result<Light, SceneError> {
+    let kind = fields.required_string("type")?;
+    let kinds = [
+        "ambient",
+        "point",
+        "directional",
+        "spot",
+        "rect",
+        "disk",
+        "sphere",
+    ];
+    if !kinds.contains(&kind.as_str()) {
+        return Err(fields.error(format!(
+            "has an unknown type, expected ambient, point, directional, spot, rect, disk or \
+             sphere, found `{}`",
+            kind
+        )));
+    }
+    fields.what = format!("{} light", kind);
+    let intensity = fields.required(Fields::number, "intensity")?;
+    let mut light = match kind.as_str() {
+        "ambient" => Light::ambient(intensity),
+        "point" => Light::point(intensity, fields.required(Fields::vec3, "position")?),
+        "directional" => {
+            Light::directional(intensity, fields.required(Fields::direction, "direction")?)
+        }
+        "spot" => {
+            let position = fields.required(Fields::vec3, "position")?;
+            let direction = fields.required(Fields::direction, "direction")?;
+            let inner_angle = fields.required(Fields::angle, "inner_angle")?;
+            let outer_angle = fields.required(Fields::angle, "outer_angle")?;
+            if inner_angle > outer_angle {
+                return Err(fields.error("has an `inner_angle` wider than its `outer_angle`"));
+            }
+            Light::spot(
+                intensity,
+                position,
+                direction,
+                inner_angle.to_radians(),
+                outer_angle.to_radians(),
+            )
+        }
+        "rect" => {
+            let corner = fields.required(Fields::vec3, "corner")?;
+            let u = fields.required(Fields::vec3, "u")?;
+            let v = fields.required(Fields::vec3, "v")?;
+            if u.cross(&v) == Vec3::ZERO {
+                return Err(fields.error("has parallel `u` and `v`, so it has no area"));
+            }
+            Light::rect(intensity, corner, u, v)
+        }
+        "disk" => Light::disk(
+            intensity,
+            fields.required(Fields::vec3, "center")?,
+            fields.required(Fields::direction, "normal")?,
+            fields.required(Fields::positive, "radius")?,
+        ),
+        "sphere" => Light::sphere(
+            intensity,
+            fields.required(Fields::vec3, "center")?,
+            fields.required(Fields::positive, "radius")?,
+        ),
+        _ => unreachable!("light types are checked above"),
+    };
+    if let Some(color) = fields.color("color")? {
+        light = light.with_color(color);
+    }
+    // Keys that don't apply are left unread and reported as unknown
+    if let Light::PointL(_) | Light::SpotL(_) | Light::AreaL(_) = light {
+        if let Some(attenuation) = fields.attenuation("attenuation")? {
+            light = light.with_attenuation(attenuation);
+        }
+    }
+    if let Light::AreaL(_) = light {
+        match fields.integer("samples")? {
+            Some(0) => return Err(fields.error("needs at least one sample")),
+            Some(samples) => light = light.with_samples(samples as usize),
+            None => {}
+        }
+    }
+    fields.finish()?;
+    Ok(light)
+}
+
+/// Typed access to the entries of a table, remembering which were read so
+/// the rest can be reported as unknown.
+struct Fields<'a> {
+    table: &'a Table,
+    /// What the table describes, for error messages.
+    what: String,
+    read: Vec<bool>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(table: &'a Table, what: &str) -> Fields<'a> {
+        Fields {
+            table,
+            what: what.to_string(),
+            read: vec![false; table.entries.len()],
+        }
+    }
+
+    fn error(&self, message: impl fmt::Display) -> SceneError {
+        SceneError::parse(self.table.line, format!("{} {}", self.what, message))
+    }
+
+    fn has(&self, key: &str) -> bool {
+        self.table.entries.iter().any(|entry| entry.key == key)
+    }
+
+    fn get(&mut self, key: &str) -> Option<&'a Entry> {
+        let i = self
+            .table
+            .entries
+            .iter()
+            .position(|entry| entry.key == key)?;
+        self.read[i] = true;
+        Some(&self.table.entries[i])
+    }
+
+    fn required<T>(
+        &mut self,
+        get: fn(&mut Self, &str) -> Result<Option<T>, SceneError>,
+        key: &str,
+    ) -> Result<T, SceneError> {
+        get(self, key)?.ok_or_else(|| self.error(format!("is missing `{}`", key)))
+    }
+
+    fn number(&mut self, key: &str) -> Result<Option<f64>, SceneError> {
+        self.get(key).map(|entry| entry.number()).transpose()
+    }
+
+    /// A number for which `valid` holds, described by `expected` otherwise.
+    fn number_where(
+        &mut self,
+        key: &str,
+        valid: fn(f64) -> bool,
+        expected: &str,
+    ) -> Result<Option<f64>, SceneError> {
+        match self.get(key) {
+            Some(entry) => match entry.number()? {
+                n if valid(n) => Ok(Some(n)),
+                n => Err(entry.error(format!("`{}` expects {}, found {}", entry.key, expected, n))),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn positive(&mut self, key: &str) -> Result<Option<f64>, SceneError> {
+        self.number_where(key, |n| n > 0.0, "a positive number")
+    }
+
+    fn fraction(&mut self, key: &str) -> Result<Option<f64>, SceneError> {
+        self.number_where(key, |n| (0.0..=1.0).contains(&n), "a number from 0 to 1")
+    }
+
+    /// Degrees from the axis of a cone.
+    fn angle(&mut self, key: &str) -> Result<Option<f64>, SceneError> {
+        self.number_where(
+            key,
+            |n| (0.0..=180.0).contains(&n),
+            "an angle from 0 to 180",
+        )
+    }
+
+    fn integer(&mut self, key: &str) -> Result<Option<u32>, SceneError> {
+        match self.get(key) {
+            Some(entry) => match entry.number()? {
+                n if n >= 0.0 && n.fract() == 0.0 && n <= u32::MAX as f64 => Ok(Some(n as u32)),
+                n => Err(entry.error(format!(
+                    "`{}` expects a whole number, found {}",
+                    entry.key, n
+                ))),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// A specular exponent, or `-1` for matte.
+    fn specular(&mut self, key: &str) -> Result<Option<i32>, SceneError> {
+        match self.get(key) {
+            Some(entry) => match entry.number()? {
+                n if n >= -1.0 && n.fract() == 0.0 && n <= i32::MAX as f64 => Ok(Some(n as i32)),
+                n => Err(entry.error(format!(
+                    "`{}` expects a whole number, or -1 for matte, found {}",
+                    entry.key, n
+                ))),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn boolean(&mut self, key: &str) -> Result<Option<bool>, SceneError> {
+        match self.get(key) {
+            Some(Entry {
+                value: Value::Bool(b),
+                ..
+            }) => Ok(Some(*b)),
+            Some(entry) => Err(entry.mismatch("true or false")),
+            None => Ok(None),
+        }
+    }
+
+    fn string(&mut self, key: &str) -> Result<Option<String>, SceneError> {
+        match self.get(key) {
+            Some(Entry {
+                value: Value::String(s),
+                ..
+            }) => Ok(Some(s.clone())),
+            Some(entry) => Err(entry.mismatch("a string")),
+            None => Ok(None),
+        }
+    }
+
+    fn required_string(&mut self, key: &str) -> Result<String, SceneError> {
+        self.required(Fields::string, key)
+    }
+
+    fn numbers<const N: usize>(&mut self, key: &str) -> Result<Option<[f64; N]>, SceneError> {
+        self.get(key).map(|entry| entry.numbers()).transpose()
+    }
+
+    fn vec3(&mut self, key: &str) -> Result<Option<Vec3>, SceneError> {
+        Ok(self.numbers(key)?.map(|[x, y, z]| Vec3::new(x, y, z)))
+    }
+
+    /// A vector that isn't zero, such as a normal.
+    fn direction(&mut self, key: &str) -> Result<Option<Vec3>, SceneError> {
+        match self.get(key) {
+            Some(entry) => match entry.numbers()? {
+                [x, y, z] if x != 0.0 || y != 0.0 || z != 0.0 => Ok(Some(Vec3::new(x, y, z))),
+                _ => Err(entry.error(format!("`{}` can't be zero", entry.key))),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn color(&mut self, key: &str) -> Result<Option<Rgb>, SceneError> {
+        Ok(self
+            .numbers(key)?
+            .map(|[red, green, blue]| Rgb { red, green, blue }))
+    }
+
+    /// Three points, as an array of three arrays.
+    fn points(&mut self, key: &str) -> Result<Option<[Vec3; 3]>, SceneError> {
+        let entry = match self.get(key) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let rows = match &entry.value {
+            Value::Array(rows) if rows.len() == 3 => rows,
+            _ => return Err(entry.mismatch("an array of 3 points")),
+        };
+        let mut points = [Vec3::ZERO; 3];
+        for (point, row) in points.iter_mut().zip(rows) {
+            let row = Entry {
+                key: entry.key.clone(),
+                value: row.clone(),
+                line: entry.line,
+            };
+            let [x, y, z] = row.numbers()?;
+            *point = Vec3::new(x, y, z);
+        }
+        Ok(Some(points))
+    }
+
+    /// A matrix, as an array of its three rows.
+    fn rows(&mut self, key: &str) -> Result<Option<Mat3>, SceneError> {
+        Ok(self
+            .points(key)?
+            .map(|[a, b, c]| Mat3::new(a.x, a.y, a.z, b.x, b.y, b.z, c.x, c.y, c.z)))
+    }
+
+    fn attenuation(&mut self, key: &str) -> Result<Option<Attenuation>, SceneError> {
+        let entry = match self.get(key) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let attenuation = match &entry.value {
+            Value::String(s) if s == "none" => Attenuation::None,
+            Value::String(s) if s == "inverse_square" => Attenuation::InverseSquare,
+            Value::Array(_) => {
+                let [constant, linear, quadratic] = entry.numbers()?;
+                let coefficients = [constant, linear, quadratic];
+                if coefficients.iter().any(|&k| k < 0.0) || coefficients.iter().all(|&k| k == 0.0) {
+                    return Err(entry.error(format!(
+                        "`{}` expects coefficients of at least 0, not all 0",
+                        entry.key
+                    )));
+                }
+                Attenuation::Polynomial {
+                    constant,
+                    linear,
+                    quadratic,
+                }
+            }
+            _ => {
+                return Err(
+                    entry.mismatch("\"none\", \"inverse_square\" or [constant, linear, quadratic]")
+                )
+            }
+        };
+        Ok(Some(attenuation))
+    }
+
+    /// Fails on the first entry that wasn't read.
+    fn finish(self) -> Result<(), SceneError> {
+        match self.read.iter().position(|read| !read) {
+            Some(i) => {
+                let entry = &self.table.entries[i];
+                Err(entry.error(format!("unknown key `{}` for {}", entry.key, self.what)))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Number(f64),
+    Bool(bool),
+    String(String),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn describe(&self) -> String {
+        match self {
+            Value::Number(n) => format!("the number {}", n),
+            Value::Bool(b) => format!("{}", b),
+            Value::String(s) => format!("the string {:?}", s),
+            Value::Array(values) => format!("an array of {}", values.len()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    key: String,
+    value: Value,
+    line: usize,
+}
+
+impl Entry {
+    fn error(&self, message: impl Into<String>) -> SceneError {
+        SceneError::parse(self.line, message)
+    }
+
+    fn mismatch(&self, expected: &str) -> SceneError {
+        self.error(format!(
+            "`{}` expects {}, found {}",
+            self.key,
+            expected,
+            self.value.describe()
+        ))
+    }
+
+    fn number(&self) -> Result<f64, SceneError> {
+        match self.value {
+            Value::Number(n) => Ok(n),
+            _ => Err(self.mismatch("a number")),
+        }
+    }
+
+    fn numbers<const N: usize>(&self) -> Result<[f64; N], SceneError> {
+        let mut numbers = [0.0; N];
+        match &self.value {
+            Value::Array(values) if values.len() == N => {
+                for (number, value) in numbers.iter_mut().zip(values) {
+                    match value {
+                        Value::Number(n) => *number = *n,
+                        _ => return Err(self.mismatch(&format!("an array of {} numbers", N))),
+                    }
+                }
+                Ok(numbers)
+            }
+            _ => Err(self.mismatch(&format!("an array of {} numbers", N))),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Table {
+    /// Empty for the keys before the first header.
+    name: String,
+    /// Whether the header was `[[name]]`, one of many tables with that name.
+    array: bool,
+    line: usize,
+    entries: Vec<Entry>,
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+fn is_bare(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Parser<'a> {
+        Parser {
+            chars: source.chars().peekable(),
+            line: 1,
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> SceneError {
+        SceneError::parse(self.line, message)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        c
+    }
+
+    /// Skips spaces and comments, and line breaks too if `newlines` is set.
+    fn skip(&mut self, newlines: bool) {
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                '#' => {
+                    while self.chars.peek().is_some_and(|&c| c != '\n') {
+                        self.bump();
+                    }
+                }
+                '\n' | '\r' if !newlines => break,
+                c if c.is_whitespace() => {
+                    self.bump();
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SceneError> {
+        match self.chars.peek() {
+            Some(&c) if c == expected => {
+                self.bump();
+                Ok(())
+            }
+            Some(&c) => Err(self.error(format!("expected `{}`, found `{}`", expected, c))),
+            None => Err(self.error(format!(
+                "expected `{}`, found the end of the file",
+                expected
+            ))),
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), SceneError> {
+        self.skip(false);
+        match self.chars.peek() {
+            None | Some('\n') | Some('\r') => Ok(()),
+            Some(&c) => Err(self.error(format!("expected the end of the line, found `{}`", c))),
+        }
+    }
+
+    fn document(mut self) -> Result<Vec<Table>, SceneError> {
+        let mut tables = vec![Table {
+            name: String::new(),
+            array: false,
+            line: 1,
+            entries: Vec::new(),
+        }];
+        loop {
+            self.skip(true);
+            match self.chars.peek() {
+                None => return Ok(tables),
+                Some('[') => {
+                    let table = self.header()?;
+                    let previous = tables
+                        .iter()
+                        .find(|t| t.name == table.name && !(t.array && table.array));
+                    if let Some(previous) = previous {
+                        return Err(self.error(format!(
+                            "table `{}` is already defined on line {}",
+                            table.name, previous.line
+                        )));
+                    }
+                    tables.push(table);
+                }
+                Some(_) => {
+                    let entry = self.entry()?;
+                    let table = tables.last_mut().unwrap();
+                    if let Some(previous) = table.entries.iter().find(|e| e.key == entry.key) {
+                        return Err(self.error(format!(
+                            "`{}` is already set on line {}",
+                            entry.key, previous.line
+                        )));
+                    }
+                    table.entries.push(entry);
+                }
+            }
+            self.end_of_line()?;
+        }
+    }
+
+    fn header(&mut self) -> Result<Table, SceneError> {
+        let line = self.line;
+        self.expect('[')?;
+        let array = self.chars.peek() == Some(&'[');
+        if array {
+            self.bump();
+        }
+        self.skip(false);
+        let mut name = self.key()?;
+        while self.chars.peek() == Some(&'.') {
+            self.bump();
+            name.push('.');
+            name += &self.key()?;
+        }
+        self.skip(false);
+        self.expect(']')?;
+        if array {
+            self.expect(']')?;
+        }
+        Ok(Table {
+            name,
+            array,
+            line,
+            entries: Vec::new(),
+        })
+    }
+
+    fn key(&mut self) -> Result<String, SceneError> {
+        let mut key = String::new();
+        while let Some(&c) = self.chars.peek().filter(|&&c| is_bare(c)) {
+            key.push(c);
+            self.bump();
+        }
+        if key.is_empty() {
+            return Err(match self.chars.peek() {
+                Some(&c) => self.error(format!("expected a name, found `{}`", c)),
+                None => self.error("expected a name, found the end of the file"),
+            });
+        }
+        Ok(key)
+    }
+
+    fn entry(&mut self) -> Result<Entry, SceneError> {
+        let line = self.line;
+        let key = self.key()?;
+        self.skip(false);
+        self.expect('=')?;
+        self.skip(false);
+        let value = self.value()?;
+        Ok(Entry { key, value, line })
+    }
+
+    fn value(&mut self) -> Result<Value, SceneError> {
+        match self.chars.peek() {
+            Some('"') => self.string(),
+            Some('[') => self.array(),
+            Some(&c) if is_bare(c) || c == '+' || c == '.' => {
+                let mut word = String::new();
+                while let Some(&c) = self
+                    .chars
+                    .peek()
+                    .filter(|&&c| is_bare(c) || c == '+' || c == '.')
+                {
+                    word.push(c);
+                    self.bump();
+                }
+                match word.as_str() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    _ => match word.replace('_', "").parse::<f64>() {
+                        Ok(n) if n.is_finite() => Ok(Value::Number(n)),
+                        Ok(_) => {
+                            Err(self.error(format!("expected a finite number, found `{}`", word)))
+                        }
+                        Err(_) => Err(self.error(format!(
+                            "expected a number, string, boolean or array, found `{}`",
+                            word
+                        ))),
+                    },
+                }
+            }
+            Some(&c) => Err(self.error(format!("expected a value, found `{}`", c))),
+            None => Err(self.error("expected a value, found the end of the file")),
+        }
+    }
+
+    fn string(&mut self) -> Result<Value, SceneError> {
+        let line = self.line;
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(Value::String(s)),
+                Some('\\') => match self.bump() {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some(c) => return Err(self.error(format!("unknown escape `\\{}`", c))),
+                    None => return Err(SceneError::parse(line, "unterminated string")),
+                },
+                Some('\n') | None => return Err(SceneError::parse(line, "unterminated string")),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    /// Arrays may span several lines and end with a comma.
+    fn array(&mut self) -> Result<Value, SceneError> {
+        let line = self.line;
+        self.expect('[')?;
+        let mut values = Vec::new();
+        loop {
+            self.skip(true);
+            if self.chars.peek() == Some(&']') {
+                self.bump();
+                return Ok(Value::Array(values));
+            }
+            values.push(self.value()?);
+            self.skip(true);
+            match self.chars.peek() {
+                Some(',') => {
+                    self.bump();
+                }
+                Some(']') => {}
+                Some(&c) if self.line > line => {
+                    return Err(self.error(format!(
+                        "expected `,` or `]`, found `{}`, the array from line {} is unterminated",
+                        c, line
+                    )))
+                }
+                Some(&c) => return Err(self.error(format!("expected `,` or `]`, found `{}`", c))),
+                None => return Err(SceneError::parse(line, "unterminated array")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::Ray;
+
+    fn parse_str(source: &str) -> Result<World, SceneError> {
+        parse(source, Path::new(""))
+    }
+
+    fn error(source: &str) -> String {
+        parse_str(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn shipped_scenes_load() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes");
+        let mut count = 0;
+        for file in fs::read_dir(directory).unwrap() {
+            let path = file.unwrap().path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "toml")
+            {
+                let world = load(&path).unwrap_or_else(|e| panic!("{}", e));
+                assert!(!world.scene.objects().is_empty(), "{}", path.display());
+                count += 1;
+            }
+        }
+        assert!(count >= 7);
+    }
+
+    #[test]
+    fn values_arrays_and_comments() {
+        let world = parse_str(
+            r#"
+# Materials may come after the objects using them
+[[object]]
+type = "sphere"   # a comment
+center = [
+    0, 0,
+    3,  # trailing commas are fine
+]
+radius = 1_000.5
+material = "red"
+
+[materials.red]
+color = [255, 0, 0]
+specular = -1
+
+[viewport]
+width = 2
+height = 1.0
+"#,
+        )
+        .unwrap();
+        assert_eq!(world.viewport, Viewport::new(2.0, 1.0, 1.0));
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -2000.0), Vec3::new(0.0, 0.0, 1.0));
+        let hit = world
+            .closest_intersection(&ray, 0.0, f64::INFINITY)
+            .unwrap();
+        assert_eq!(hit.material.color, Rgb::from_ints(255, 0, 0));
+        assert_eq!(hit.material.specular, -1);
+    }
+
+    #[test]
+    fn syntax_errors_name_their_line() {
+        assert_eq!(
+            error("[camera]\nposition = [0, 1,\n  2\n"),
+            "2: unterminated array"
+        );
+        assert_eq!(
+            error("[camera]\nposition = [0, 1, 2\nlook_at = [0, 0, 1]\n"),
+            "3: expected `,` or `]`, found `l`, the array from line 2 is unterminated"
+        );
+        assert_eq!(
+            error("[camera]\nposition = [0 1 2]\n"),
+            "2: expected `,` or `]`, found `1`"
+        );
+        assert_eq!(error("[camera\n"), "1: expected `]`, found `\n`");
+        assert_eq!(error("\n\nkey = \"open\n"), "3: unterminated string");
+        assert_eq!(
+            error("[camera]\n[camera]\n"),
+            "2: table `camera` is already defined on line 1"
+        );
+        assert_eq!(
+            error("[camera]\nposition = [0, 0, 0]\nposition = [1, 0, 0]\n"),
+            "3: `position` is already set on line 2"
+        );
+        assert_eq!(
+            error("[camera]\nposition = [0, 0, 0] [1]\n"),
+            "2: expected the end of the line, found `[`"
+        );
+    }
+
+    #[test]
+    fn unknown_keys_and_tables_are_errors() {
+        assert_eq!(
+            error("[[object]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\ncolor = [1, 1, 1]\nradios = 2\n"),
+            "6: unknown key `radios` for sphere"
+        );
+        assert_eq!(
+            error("[camera]\nfocus = 2\n"),
+            "2: unknown key `focus` for camera"
+        );
+        assert_eq!(
+            error("\n[lights]\n"),
+            "2: unknown table `lights`, expected camera, viewport, materials.NAME, object or light"
+        );
+        assert_eq!(
+            error("[[object]]\ntype = \"cube\"\n"),
+            "1: object has an unknown type, expected sphere, plane, quad, disk, triangle or \
+             mesh, found `cube`"
+        );
+    }
+
+    #[test]
+    fn missing_keys_are_errors() {
+        assert_eq!(
+            error("\n[[object]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\n"),
+            "2: sphere needs a `color` or a `material`"
+        );
+        assert_eq!(
+            error("[[object]]\ntype = \"sphere\"\nradius = 1\nspecular = 10\n"),
+            "1: sphere needs a `color` for its material"
+        );
+        assert_eq!(
+            error("[[object]]\ntype = \"sphere\"\ncolor = [1, 1, 1]\nradius = 1\n"),
+            "1: sphere is missing `center`"
+        );
+        assert_eq!(
+            error("[[object]]\ntype = \"sphere\"\nmaterial = \"gold\"\n"),
+            "1: sphere uses an undefined material `gold`"
+        );
+        assert_eq!(
+            error("[[light]]\nintensity = 1\n"),
+            "1: light is missing `type`"
+        );
+    }
+
+    #[test]
+    fn wrong_value_types_are_errors() {
+        assert_eq!(
+            error("[camera]\nposition = [0, 1]\n"),
+            "2: `position` expects an array of 3 numbers, found an array of 2"
+        );
+        assert_eq!(
+            error("[camera]\nposition = \"origin\"\n"),
+            "2: `position` expects an array of 3 numbers, found the string \"origin\""
+        );
+        assert_eq!(
+            error("[[object]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\ncolor = [1, 1, 1]\nradius = true\n"),
+            "5: `radius` expects a number, found true"
+        );
+        assert_eq!(
+            error(
+                "[[object]]\ntype = \"quad\"\ncolor = [1, 1, 1]\ncorner = [0, 0, 0]\n\
+                 u = [1, 0, 0]\nv = [0, 1, 0]\none_sided = 1\n"
+            ),
+            "7: `one_sided` expects true or false, found the number 1"
+        );
+        assert_eq!(
+            error("[[light]]\ntype = 1\n"),
+            "2: `type` expects a string, found the number 1"
+        );
+        assert_eq!(
+            error("[camera]\nposition = [0, 0, zero]\n"),
+            "2: expected a number, string, boolean or array, found `zero`"
+        );
+    }
+
+    #[test]
+    fn specular_takes_whole_numbers_or_minus_one() {
+        let sphere = |specular: &str| {
+            format!(
+                "[[object]]\ntype = \"sphere\"\ncenter = [0, 0, 3]\nradius = 1\n\
+                 color = [1, 1, 1]\nspecular = {}\n",
+                specular
+            )
+        };
+        assert!(parse_str(&sphere("-1")).is_ok());
+        assert!(parse_str(&sphere("0")).is_ok());
+        assert!(parse_str(&sphere("500")).is_ok());
+        assert_eq!(
+            error(&sphere("-2")),
+            "6: `specular` expects a whole number, or -1 for matte, found -2"
+        );
+        assert_eq!(
+            error(&sphere("2.5")),
+            "6: `specular` expects a whole number, or -1 for matte, found 2.5"
+        );
+    }
+
+    #[test]
+    fn degenerate_values_are_errors() {
+        let object = |keys: &str| error(&format!("[[object]]\ncolor = [1, 1, 1]\n{}", keys));
+        assert_eq!(
+            object("type = \"sphere\"\ncenter = [0, 0, 0]\nradius = 0\n"),
+            "5: `radius` expects a positive number, found 0"
+        );
+        assert_eq!(
+            object("type = \"disk\"\ncenter = [0, 0, 0]\nnormal = [0, 1, 0]\nradius = -1\n"),
+            "6: `radius` expects a positive number, found -1"
+        );
+        assert_eq!(
+            object("type = \"plane\"\npoint = [0, 0, 0]\nnormal = [0, 0, 0]\n"),
+            "5: `normal` can't be zero"
+        );
+        assert_eq!(
+            object("type = \"quad\"\ncorner = [0, 0, 0]\nu = [1, 0, 0]\nv = [2, 0, 0]\n"),
+            "1: quad has parallel `u` and `v`, so it has no area"
+        );
+        assert_eq!(
+            object("type = \"triangle\"\nvertices = [[0, 0, 0], [1, 1, 1], [2, 2, 2]]\n"),
+            "1: triangle has `vertices` on a line, so it has no area"
+        );
+        assert_eq!(
+            object("type = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nreflective = 1.5\n"),
+            "6: `reflective` expects a number from 0 to 1, found 1.5"
+        );
+
+        assert_eq!(
+            error("[camera]\nposition = [1, 2, 3]\nlook_at = [1, 2, 3]\n"),
+            "1: camera looks at its own `position`, `look_at` must be elsewhere"
+        );
+        assert_eq!(
+            error("[viewport]\nwidth = 0\n"),
+            "2: `width` expects a positive number, found 0"
+        );
+        assert_eq!(
+            error("[viewport]\nheight = -1\n"),
+            "2: `height` expects a positive number, found -1"
+        );
+        assert_eq!(
+            error("[viewport]\nfov = 180\n"),
+            "2: `fov` expects an angle between 0 and 180, found 180"
+        );
+        assert_eq!(
+            error("[[light]]\ntype = \"point\"\nintensity = 1\nposition = [0, 0, 0]\nattenuation = [0, 0, 0]\n"),
+            "5: `attenuation` expects coefficients of at least 0, not all 0"
+        );
+        assert_eq!(
+            error("[[light]]\ntype = \"directional\"\nintensity = 1\ndirection = [0, 0, 0]\n"),
+            "4: `direction` can't be zero"
+        );
+    }
+
+    #[test]
+    fn numbers_must_be_finite() {
+        for &number in &["inf", "-inf", "+infinity", "nan", "NaN", "1e999"] {
+            assert_eq!(
+                error(&format!("[camera]\nposition = [0, {}, 0]\n", number)),
+                format!("2: expected a finite number, found `{}`", number)
+            );
+        }
+        assert_eq!(
+            error("[[light]]\ntype = \"ambient\"\nintensity = nan\n"),
+            "3: expected a finite number, found `nan`"
+        );
+    }
+
+    #[test]
+    fn shutters_open_and_close_within_the_frame() {
+        let camera = |shutter: &str| parse_str(&format!("[camera]\nshutter = {}\n", shutter));
+        for &shutter in &["[0, 1]", "[0.25, 0.75]", "[0.5, 0.5]"] {
+            assert!(camera(shutter).is_ok(), "{}", shutter);
+        }
+        for &shutter in &["[0.75, 0.25]", "[-0.5, 0.5]", "[0, 2]"] {
+            assert_eq!(
+                camera(shutter).unwrap_err().to_string(),
+                format!(
+                    "2: `shutter` expects times from 0 to 1, opening before it closes, found {}",
+                    shutter
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn rect_lights_need_an_area() {
+        let rect = |u: &str, v: &str| {
+            format!(
+                "[[light]]\ntype = \"rect\"\nintensity = 1\ncorner = [0, 0, 0]\n\
+                 u = {}\nv = {}\n",
+                u, v
+            )
+        };
+        assert!(parse_str(&rect("[1, 0, 0]", "[0, 0, 1]")).is_ok());
+        for &(u, v) in &[
+            ("[1, 0, 0]", "[2, 0, 0]"),
+            ("[1, 0, 0]", "[-1, 0, 0]"),
+            ("[0, 0, 0]", "[0, 0, 1]"),
+        ] {
+            assert_eq!(
+                error(&rect(u, v)),
+                "1: rect light has parallel `u` and `v`, so it has no area"
+            );
+        }
+    }
+
+    #[test]
+    fn spotlight_cones_must_be_valid() {
+        let spot = |inner: &str, outer: &str| {
+            format!(
+                "[[light]]\ntype = \"spot\"\nintensity = 1\nposition = [0, 0, 0]\n\
+                 direction = [0, 0, 1]\ninner_angle = {}\nouter_angle = {}\n",
+                inner, outer
+            )
+        };
+        let world = parse_str(&spot("10", "10")).unwrap();
+        assert!(matches!(world.scene.lights[0], Light::SpotL(_)));
+        assert_eq!(
+            error(&spot("30", "20")),
+            "1: spot light has an `inner_angle` wider than its `outer_angle`"
+        );
+        assert_eq!(
+            error(&spot("-5", "20")),
+            "6: `inner_angle` expects an angle from 0 to 180, found -5"
+        );
+        assert_eq!(
+            error(&spot("10", "200")),
+            "7: `outer_angle` expects an angle from 0 to 180, found 200"
+        );
+    }
+
+    #[test]
+    fn descriptions_tell_whether_the_width_is_set() {
+        let sets_width =
+            |source: &str| parse_description(source, Path::new("")).unwrap().sets_width;
+        assert!(!sets_width(""));
+        assert!(!sets_width("[viewport]\nheight = 2\n"));
+        assert!(!sets_width("[viewport]\nfov = 90\n"));
+        assert!(sets_width("[viewport]\nwidth = 1\n"));
+        assert!(sets_width("[viewport]\nfov = 90\naspect = 2\n"));
+    }
+
+    #[test]
+    fn errors_from_files_name_the_file() {
+        let error = load("no/such/scene.toml").unwrap_err();
+        assert!(matches!(error, SceneError::Io { .. }));
+        assert!(error.to_string().starts_with("no/such/scene.toml: "));
+
+        let error = parse_str("[viewport]\nwidth = 0\n")
+            .unwrap_err()
+            .in_file(Path::new("scene.toml"));
+        assert_eq!(
+            error.to_string(),
+            "scene.toml:2: `width` expects a positive number, found 0"
+        );
+    }
+}